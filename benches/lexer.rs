@@ -0,0 +1,64 @@
+//! Compares the owned `next_token` path with the borrowing `next_token_borrowed` path.
+//!
+//! Run with `cargo bench --bench lexer`. Uses a plain timing loop so it needs no extra
+//! dependencies.
+
+use std::{
+    hint::black_box,
+    time::{Duration, Instant},
+};
+
+use interpreter_rust::lexer::{BorrowedToken, Lexer, Token};
+
+const SNIPPET: &str = r#"let five = 5;
+let ten = 10_000;
+let add = fn(first_value, second_value) {
+    first_value + second_value;
+};
+let result = add(five, ten);
+if (result < 10.5) { return true; } else { return false; }
+"#;
+
+const REPEAT: usize = 2_000;
+const ROUNDS: u32 = 20;
+
+fn lex_owned(source: &str) -> usize {
+    let mut lexer = Lexer::new(source.to_string());
+    let mut count = 0;
+    while black_box(lexer.next_token().unwrap()) != Token::EOF {
+        count += 1;
+    }
+    count
+}
+
+fn lex_borrowed(source: &str) -> usize {
+    let mut lexer = Lexer::new(source.to_string());
+    let mut count = 0;
+    while black_box(lexer.next_token_borrowed().unwrap()) != BorrowedToken::Other(Token::EOF) {
+        count += 1;
+    }
+    count
+}
+
+/// Runs `lex` over `source` `ROUNDS` times and returns the best time per token
+fn bench(source: &str, lex: fn(&str) -> usize) -> Duration {
+    let mut best = Duration::MAX;
+    for _ in 0..ROUNDS {
+        let start = Instant::now();
+        let tokens = lex(black_box(source));
+        best = best.min(start.elapsed() / tokens as u32);
+    }
+    best
+}
+
+fn main() {
+    let source = SNIPPET.repeat(REPEAT);
+
+    assert_eq!(lex_owned(&source), lex_borrowed(&source));
+
+    println!("next_token          {:?}/token", bench(&source, lex_owned));
+    println!(
+        "next_token_borrowed {:?}/token",
+        bench(&source, lex_borrowed)
+    );
+}
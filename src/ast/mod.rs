@@ -27,6 +27,28 @@ pub enum Statement {
     Expression(ExpressionStatement),
 }
 
+impl Statement {
+    fn write_tree(&self, out: &mut String, depth: usize) {
+        match self {
+            Statement::Let(s) => {
+                write_tree_line(out, depth, "LetStatement");
+                write_tree_line(out, depth + 1, &format!("Identifier {}", s.name));
+                s.value.write_tree(out, depth + 1);
+            }
+            Statement::Return(s) => {
+                write_tree_line(out, depth, "ReturnStatement");
+                if let Some(value) = &s.return_value {
+                    value.write_tree(out, depth + 1);
+                }
+            }
+            Statement::Expression(s) => {
+                write_tree_line(out, depth, "ExpressionStatement");
+                s.expression.write_tree(out, depth + 1);
+            }
+        }
+    }
+}
+
 impl fmt::Display for Statement {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
@@ -40,24 +62,141 @@ impl fmt::Display for Statement {
 #[derive(Debug)]
 pub enum Expression {
     Identifier(Identifier),
+    IntegerLiteral(i64),
+    Boolean(bool),
+    Infix {
+        left: Box<Expression>,
+        operator: Operator,
+        right: Box<Expression>,
+    },
+}
+
+impl Expression {
+    fn write_tree(&self, out: &mut String, depth: usize) {
+        match self {
+            Expression::Identifier(i) => write_tree_line(out, depth, &format!("Identifier {}", i)),
+            Expression::IntegerLiteral(n) => {
+                write_tree_line(out, depth, &format!("IntegerLiteral {}", n))
+            }
+            Expression::Boolean(b) => write_tree_line(out, depth, &format!("Boolean {}", b)),
+            Expression::Infix {
+                left,
+                operator,
+                right,
+            } => {
+                write_tree_line(out, depth, &format!("Infix {}", operator));
+                left.write_tree(out, depth + 1);
+                right.write_tree(out, depth + 1);
+            }
+        }
+    }
 }
 
 impl fmt::Display for Expression {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
             Expression::Identifier(i) => i.fmt(f),
+            Expression::IntegerLiteral(n) => n.fmt(f),
+            Expression::Boolean(b) => b.fmt(f),
+            Expression::Infix {
+                left,
+                operator,
+                right,
+            } => write!(f, "({} {} {})", left, operator, right),
         }
     }
 }
 
+/// Operator of a prefix or infix expression, so the AST doesn't carry operators as strings
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum Operator {
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Mod,
+    Eq,
+    NotEq,
+    Lt,
+    Gt,
+    LtEq,
+    GtEq,
+    And,
+    Or,
+    Not,
+    Neg,
+}
+
+impl Operator {
+    /// Maps a token in prefix position, where `-` negates instead of subtracting
+    pub fn prefix(token: &Token) -> Option<Operator> {
+        match token {
+            Token::Bang => Some(Operator::Not),
+            Token::Minus => Some(Operator::Neg),
+            _ => None,
+        }
+    }
+
+    /// Maps a token in infix position. `!` only exists as a prefix operator, so like any other
+    /// non-operator token it has no infix operator.
+    pub fn infix(token: &Token) -> Option<Operator> {
+        let operator = match token {
+            Token::Plus => Operator::Add,
+            Token::Minus => Operator::Sub,
+            Token::Asterisk => Operator::Mul,
+            Token::Slash => Operator::Div,
+            Token::Percent => Operator::Mod,
+            Token::Equal => Operator::Eq,
+            Token::NotEqual => Operator::NotEq,
+            Token::LessThan => Operator::Lt,
+            Token::GreaterThan => Operator::Gt,
+            Token::LessEqual => Operator::LtEq,
+            Token::GreaterEqual => Operator::GtEq,
+            Token::And => Operator::And,
+            Token::Or => Operator::Or,
+            _ => return None,
+        };
+        Some(operator)
+    }
+}
+
+impl fmt::Display for Operator {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let operator = match self {
+            Operator::Add => "+",
+            Operator::Sub | Operator::Neg => "-",
+            Operator::Mul => "*",
+            Operator::Div => "/",
+            Operator::Mod => "%",
+            Operator::Eq => "==",
+            Operator::NotEq => "!=",
+            Operator::Lt => "<",
+            Operator::Gt => ">",
+            Operator::LtEq => "<=",
+            Operator::GtEq => ">=",
+            Operator::And => "&&",
+            Operator::Or => "||",
+            Operator::Not => "!",
+        };
+        write!(f, "{}", operator)
+    }
+}
+
 #[derive(Debug)]
 pub struct Program {
     pub statements: Vec<Statement>,
+    /// Indices of statements that followed a blank line in the source, in increasing order, kept
+    /// as a layout hint
+    pub blank_lines: Vec<usize>,
 }
 
 impl fmt::Display for Program {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        for stmt in &self.statements {
+        let mut blank_lines = self.blank_lines.iter().peekable();
+        for (idx, stmt) in self.statements.iter().enumerate() {
+            if blank_lines.next_if_eq(&&idx).is_some() {
+                write!(f, "\n\n")?;
+            }
             write!(f, "{}", stmt)?;
         }
         Ok(())
@@ -65,6 +204,17 @@ impl fmt::Display for Program {
 }
 
 impl Program {
+    /// Renders the structure of the program as an indented tree, one node per line. Unlike
+    /// `Display` this is meant for inspecting the parse, not for reconstructing source.
+    pub fn debug_tree(&self) -> String {
+        let mut out = String::new();
+        write_tree_line(&mut out, 0, "Program");
+        for stmt in &self.statements {
+            stmt.write_tree(&mut out, 1);
+        }
+        out
+    }
+
     fn token_literal(&self) -> &str {
         if !self.statements.is_empty() {
             match &self.statements[0] {
@@ -78,6 +228,12 @@ impl Program {
     }
 }
 
+fn write_tree_line(out: &mut String, depth: usize, line: &str) {
+    out.push_str(&"  ".repeat(depth));
+    out.push_str(line);
+    out.push('\n');
+}
+
 #[derive(Debug)]
 pub struct Identifier {
     pub token: Token,
@@ -120,7 +276,8 @@ impl fmt::Display for LetStatement {
 #[derive(Debug)]
 pub struct ReturnStatement {
     pub token: Token,
-    pub return_value: Expression,
+    // None for a bare `return;`
+    pub return_value: Option<Expression>,
 }
 
 impl ReturnStatement {
@@ -132,7 +289,10 @@ impl ReturnStatement {
 
 impl fmt::Display for ReturnStatement {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        write!(f, "return {};", self.return_value)
+        match &self.return_value {
+            Some(value) => write!(f, "return {};", value),
+            None => write!(f, "return;"),
+        }
     }
 }
 
@@ -155,7 +315,31 @@ impl fmt::Display for ExpressionStatement {
     }
 }
 
+/// Statements between `{` and `}`, as used by if-expressions and function bodies
+#[derive(Debug)]
+pub struct BlockStatement {
+    pub token: Token,
+    pub statements: Vec<Statement>,
+}
+
+impl BlockStatement {
+    fn statement_node(&self) {}
+    pub fn token_literal(&self) -> &str {
+        self.token.token_literal()
+    }
+}
+
+impl fmt::Display for BlockStatement {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for statement in &self.statements {
+            statement.fmt(f)?;
+        }
+        Ok(())
+    }
+}
+
 #[cfg(test)]
+#[allow(clippy::useless_vec)]
 mod tests {
 
     use super::*;
@@ -163,29 +347,33 @@ mod tests {
 
     #[test]
     fn format_return_statement() -> Result<()> {
-        let expect = vec!["return 5;", "return 10;", "return 25;"];
+        let expect = vec!["return 5;", "return 10;", "return 25;", "return;"];
 
         let statements = vec![
             ReturnStatement {
                 token: Token::Return,
-                return_value: Expression::Identifier(Identifier {
+                return_value: Some(Expression::Identifier(Identifier {
                     token: Token::Int("5".to_string()),
                     value: "5".to_string(),
-                }),
+                })),
             },
             ReturnStatement {
                 token: Token::Return,
-                return_value: Expression::Identifier(Identifier {
+                return_value: Some(Expression::Identifier(Identifier {
                     token: Token::Int("10".to_string()),
                     value: "10".to_string(),
-                }),
+                })),
             },
             ReturnStatement {
                 token: Token::Return,
-                return_value: Expression::Identifier(Identifier {
+                return_value: Some(Expression::Identifier(Identifier {
                     token: Token::Int("25".to_string()),
                     value: "25".to_string(),
-                }),
+                })),
+            },
+            ReturnStatement {
+                token: Token::Return,
+                return_value: None,
             },
         ];
 
@@ -310,6 +498,7 @@ mod tests {
                     value: "5".to_string(),
                 }),
             })],
+            blank_lines: vec![],
         };
 
         assert_eq!(program.to_string(), expect);
@@ -334,10 +523,10 @@ mod tests {
                 }),
                 Statement::Return(ReturnStatement {
                     token: Token::Return,
-                    return_value: Expression::Identifier(Identifier {
+                    return_value: Some(Expression::Identifier(Identifier {
                         token: Token::Int("10".to_string()),
                         value: "10".to_string(),
-                    }),
+                    })),
                 }),
                 Statement::Let(LetStatement {
                     token: Token::Let,
@@ -352,12 +541,13 @@ mod tests {
                 }),
                 Statement::Return(ReturnStatement {
                     token: Token::Return,
-                    return_value: Expression::Identifier(Identifier {
+                    return_value: Some(Expression::Identifier(Identifier {
                         token: Token::Int("20".to_string()),
                         value: "20".to_string(),
-                    }),
+                    })),
                 }),
             ],
+            blank_lines: vec![],
         };
 
         assert_eq!(program.to_string(), expect);
@@ -367,9 +557,105 @@ mod tests {
     #[test]
     fn format_program_no_statements() -> Result<()> {
         let expect = "";
-        let program = Program { statements: vec![] };
+        let program = Program {
+            statements: vec![],
+            blank_lines: vec![],
+        };
 
         assert_eq!(program.to_string(), expect);
         Ok(())
     }
+
+    #[test]
+    fn operator_infix() -> Result<()> {
+        let cases = [
+            (Token::Plus, Operator::Add),
+            (Token::Minus, Operator::Sub),
+            (Token::Asterisk, Operator::Mul),
+            (Token::Slash, Operator::Div),
+            (Token::Percent, Operator::Mod),
+            (Token::Equal, Operator::Eq),
+            (Token::NotEqual, Operator::NotEq),
+            (Token::LessThan, Operator::Lt),
+            (Token::GreaterThan, Operator::Gt),
+            (Token::LessEqual, Operator::LtEq),
+            (Token::GreaterEqual, Operator::GtEq),
+            (Token::And, Operator::And),
+            (Token::Or, Operator::Or),
+        ];
+
+        for (token, expect) in cases {
+            let operator = Operator::infix(&token);
+            assert_eq!(operator, Some(expect));
+            assert_eq!(expect.to_string(), token.token_literal());
+        }
+
+        assert_eq!(Operator::infix(&Token::Comma), None);
+        assert_eq!(Operator::infix(&Token::Bang), None);
+        Ok(())
+    }
+
+    #[test]
+    fn operator_prefix() -> Result<()> {
+        assert_eq!(Operator::prefix(&Token::Bang), Some(Operator::Not));
+        assert_eq!(Operator::prefix(&Token::Minus), Some(Operator::Neg));
+        assert_eq!(Operator::prefix(&Token::Plus), None);
+        assert_eq!(Operator::Neg.to_string(), "-");
+        Ok(())
+    }
+
+    #[test]
+    fn debug_tree_program() -> Result<()> {
+        let expect = "\
+Program
+  LetStatement
+    Identifier x
+    Identifier y
+  ReturnStatement
+    Identifier x
+  ExpressionStatement
+    Identifier z
+";
+        let program = Program {
+            statements: vec![
+                Statement::Let(LetStatement {
+                    token: Token::Let,
+                    name: Identifier {
+                        token: Token::Ident("x".to_string()),
+                        value: "x".to_string(),
+                    },
+                    value: Expression::Identifier(Identifier {
+                        token: Token::Ident("y".to_string()),
+                        value: "y".to_string(),
+                    }),
+                }),
+                Statement::Return(ReturnStatement {
+                    token: Token::Return,
+                    return_value: Some(Expression::Identifier(Identifier {
+                        token: Token::Ident("x".to_string()),
+                        value: "x".to_string(),
+                    })),
+                }),
+                Statement::Expression(ExpressionStatement {
+                    token: Token::Ident("z".to_string()),
+                    expression: Expression::Identifier(Identifier {
+                        token: Token::Ident("z".to_string()),
+                        value: "z".to_string(),
+                    }),
+                }),
+            ],
+            blank_lines: vec![],
+        };
+
+        assert_eq!(program.debug_tree(), expect);
+        assert_eq!(
+            Program {
+                statements: vec![],
+                blank_lines: vec![],
+            }
+            .debug_tree(),
+            "Program\n"
+        );
+        Ok(())
+    }
 }
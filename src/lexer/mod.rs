@@ -1,95 +1,375 @@
 // src/lexer/lexer.rs
-use anyhow::Result;
+use std::{fmt, num::IntErrorKind, ops::Range};
 
-#[derive(Debug, PartialEq, Clone)]
-pub enum Token {
-    Ident(String),
-    Int(String),
-
-    Assign,
-    Plus,
-    Minus,
-    Bang,
-    Asterisk,
-    Slash,
-
-    LessThan,
-    GreaterThan,
-    Equal,
-    NotEqual,
-
-    Comma,
-    Semicolon,
-
-    LParen,
-    RParen,
-    LBrace,
-    RBrace,
-
-    Function,
-    Let,
-    True,
-    False,
-    If,
-    Else,
-    Return,
+use anyhow::{anyhow, bail, Error, Result};
+
+/// Generates `Token` and its `token_literal`/`Display` impls from a single table, so every
+/// variant gets all of them. Payload variants carry their source text. Fixed variants map to
+/// their literal and display as it, or as the variant name when the literal is empty.
+/// `Illegal` carries the offending character and has no literal.
+macro_rules! tokens {
+    (
+        payload { $($payload:ident),* $(,)? }
+        fixed { $($fixed:ident => $literal:literal),* $(,)? }
+    ) => {
+        #[derive(Debug, PartialEq, Clone)]
+        pub enum Token {
+            $($payload(String),)*
+            $($fixed,)*
+            Illegal(char),
+        }
+
+        impl Token {
+            /// Every variant without a payload, in declaration order
+            pub const FIXED: &'static [Token] = &[$(Token::$fixed),*];
+
+            pub fn token_literal(&self) -> &str {
+                match self {
+                    $(Token::$payload(s) => s,)*
+                    $(Token::$fixed => $literal,)*
+                    Token::Illegal(_) => "",
+                }
+            }
+        }
+
+        impl fmt::Display for Token {
+            fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                match self {
+                    $(Token::$payload(s) => write!(f, "{}({})", stringify!($payload), s),)*
+                    Token::Illegal(ch) => write!(f, "Illegal({})", ch),
+                    $(Token::$fixed if $literal.is_empty() => write!(f, "{}", stringify!($fixed)),)*
+                    token => write!(f, "{}", token.token_literal()),
+                }
+            }
+        }
+    };
+}
+
+tokens! {
+    payload {
+        Ident,
+        Int,
+        Float,
+        String,
+    }
+    fixed {
+        Assign => "=",
+        Plus => "+",
+        Minus => "-",
+        Bang => "!",
+        Asterisk => "*",
+        Slash => "/",
+        Percent => "%",
+
+        LessThan => "<",
+        GreaterThan => ">",
+        LessEqual => "<=",
+        GreaterEqual => ">=",
+        Equal => "==",
+        NotEqual => "!=",
+        And => "&&",
+        Or => "||",
+
+        Comma => ",",
+        Semicolon => ";",
+        Colon => ":",
+        Dot => ".",
+
+        LParen => "(",
+        RParen => ")",
+        LBrace => "{",
+        RBrace => "}",
+        LBracket => "[",
+        RBracket => "]",
+
+        Function => "fn",
+        Let => "let",
+        True => "true",
+        False => "false",
+        If => "if",
+        Else => "else",
+        Return => "return",
+
+        EOF => "",
+    }
+}
+
+#[derive(Debug)]
+pub enum LexerError {
+    UnterminatedString,
+    UnterminatedComment,
+    UnknownEscape(char),
+    MalformedNumber(String),
+    IntegerOverflow(String),
+}
+
+impl fmt::Display for LexerError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            LexerError::UnterminatedString => {
+                write!(f, "String literal is missing its closing quote")
+            }
+            LexerError::UnterminatedComment => {
+                write!(f, "Block comment is missing its closing */")
+            }
+            LexerError::UnknownEscape(ch) => {
+                write!(f, "Unknown escape sequence \\{} in string literal", ch)
+            }
+            LexerError::MalformedNumber(literal) => {
+                write!(f, "Malformed number literal {}", literal)
+            }
+            LexerError::IntegerOverflow(literal) => {
+                write!(f, "Integer literal {} does not fit in 64 bits", literal)
+            }
+        }
+    }
+}
 
+/// Coarse category of a token, for syntax highlighting and completion without an AST
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum TokenCategory {
+    Keyword,
+    Identifier,
+    Literal,
+    Operator,
+    Delimiter,
+    EndOfFile,
     Illegal,
-    EOF,
 }
 
 impl Token {
-    pub fn token_literal(&self) -> &str {
+    pub fn category(&self) -> TokenCategory {
+        match self {
+            Token::Ident(_) => TokenCategory::Identifier,
+            Token::Int(_) | Token::Float(_) | Token::String(_) | Token::True | Token::False => {
+                TokenCategory::Literal
+            }
+            Token::Assign
+            | Token::Plus
+            | Token::Minus
+            | Token::Bang
+            | Token::Asterisk
+            | Token::Slash
+            | Token::Percent
+            | Token::LessThan
+            | Token::GreaterThan
+            | Token::LessEqual
+            | Token::GreaterEqual
+            | Token::Equal
+            | Token::NotEqual
+            | Token::And
+            | Token::Or => TokenCategory::Operator,
+            Token::Comma
+            | Token::Semicolon
+            | Token::Colon
+            | Token::Dot
+            | Token::LParen
+            | Token::RParen
+            | Token::LBrace
+            | Token::RBrace
+            | Token::LBracket
+            | Token::RBracket => TokenCategory::Delimiter,
+            Token::Function | Token::Let | Token::If | Token::Else | Token::Return => {
+                TokenCategory::Keyword
+            }
+            Token::EOF => TokenCategory::EndOfFile,
+            Token::Illegal(_) => TokenCategory::Illegal,
+        }
+    }
+}
+
+/// Byte range of a token in the source
+pub type Span = Range<usize>;
+
+/// Lexes all of `source` into tokens with their spans, ending with `EOF`
+pub fn tokenize(source: &str) -> Result<Vec<(Token, Span)>> {
+    let mut lexer = Lexer::new(source.to_string());
+    let mut tokens = vec![];
+
+    loop {
+        let (token, span) = lexer.next_token_with_range()?;
+        let eof = token == Token::EOF;
+        tokens.push((token, span));
+        if eof {
+            break;
+        }
+    }
+
+    Ok(tokens)
+}
+
+/// Lexes `source` into each token's category and byte range, for syntax highlighting.
+///
+/// Whitespace and comments are skipped rather than returned as trivia, so the gaps between
+/// ranges are whitespace or comments. `EOF` is not included. Anything that fails to lex, like
+/// an unterminated string or comment, is returned as `Illegal` and lexing carries on after it.
+pub fn highlight_tokens(source: &str) -> Vec<(TokenCategory, Range<usize>)> {
+    let mut lexer = Lexer::new(source.to_string());
+    let mut tokens = vec![];
+
+    loop {
+        match lexer.next_token_with_range() {
+            Ok((Token::EOF, _)) => break,
+            Ok((token, range)) => tokens.push((token.category(), range)),
+            Err(_) => {
+                let end = lexer.position.min(source.len());
+                tokens.push((TokenCategory::Illegal, lexer.failed_start..end));
+            }
+        }
+    }
+
+    tokens
+}
+
+fn number_token(number: String) -> Token {
+    if number.contains('.') {
+        Token::Float(number)
+    } else {
+        Token::Int(number)
+    }
+}
+
+/// Token returned by `Lexer::next_token_borrowed`, with payloads borrowed from the source
+#[derive(Debug, PartialEq, Clone)]
+pub enum BorrowedToken<'a> {
+    Ident(&'a str),
+    Int(&'a str),
+    Float(&'a str),
+    Other(Token),
+}
+
+impl BorrowedToken<'_> {
+    pub fn to_token(&self) -> Token {
+        match self {
+            BorrowedToken::Ident(s) => Token::Ident(s.to_string()),
+            BorrowedToken::Int(s) => Token::Int(s.to_string()),
+            BorrowedToken::Float(s) => Token::Float(s.to_string()),
+            BorrowedToken::Other(token) => token.clone(),
+        }
+    }
+}
+
+/// Token as read from the input, with identifier and number payloads left as ranges into it so
+/// `next_token` can allocate them and `next_token_borrowed` can slice them
+#[derive(Debug)]
+enum RawToken {
+    Ident(Range<usize>),
+    Int(Range<usize>),
+    Float(Range<usize>),
+    Other(Token),
+}
+
+impl RawToken {
+    fn borrow<'a>(&self, input: &'a str) -> BorrowedToken<'a> {
+        match self {
+            RawToken::Ident(range) => BorrowedToken::Ident(&input[range.clone()]),
+            RawToken::Int(range) => BorrowedToken::Int(&input[range.clone()]),
+            RawToken::Float(range) => BorrowedToken::Float(&input[range.clone()]),
+            RawToken::Other(token) => BorrowedToken::Other(token.clone()),
+        }
+    }
+
+    fn to_token(&self, input: &str) -> Token {
+        match self {
+            RawToken::Ident(range) => Token::Ident(input[range.clone()].to_string()),
+            RawToken::Int(range) => Token::Int(input[range.clone()].to_string()),
+            RawToken::Float(range) => Token::Float(input[range.clone()].to_string()),
+            RawToken::Other(token) => token.clone(),
+        }
+    }
+
+    /// Like `to_token`, but moves an already built token out instead of cloning it
+    fn into_token(self, input: &str) -> Token {
         match self {
-            Token::Ident(s) => s,
-            Token::Int(s) => s,
-            Token::Assign => "=",
-            Token::Plus => "+",
-            Token::Minus => "-",
-            Token::Bang => "!",
-            Token::Asterisk => "*",
-            Token::Slash => "/",
-            Token::LessThan => "<",
-            Token::GreaterThan => ">",
-            Token::Equal => "==",
-            Token::NotEqual => "!=",
-            Token::Comma => ",",
-            Token::Semicolon => ";",
-            Token::LParen => "(",
-            Token::RParen => ")",
-            Token::LBrace => "{",
-            Token::RBrace => "}",
-            Token::Function => "fn",
-            Token::Let => "let",
-            Token::True => "true",
-            Token::False => "false",
-            Token::If => "if",
-            Token::Else => "else",
-            Token::Return => "return",
-            Token::EOF => "",
-            _ => unreachable!(
-                "This should not happen when searching for token literal, got {:?}",
-                &self
-            ),
+            RawToken::Other(token) => token,
+            raw => raw.to_token(input),
         }
     }
 }
 
+/// Optional lexer behaviour. The default lexes plain Monkey.
+#[derive(Debug, Default, Clone)]
+pub struct LexerConfig {
+    /// Lex the identifiers `and`, `or` and `not` as `And`, `Or` and `Bang`
+    pub word_operators: bool,
+}
+
+/// Byte iterator a streaming lexer pulls its input from
+struct ByteSource<'a> {
+    bytes: Box<dyn Iterator<Item = u8> + 'a>,
+    // Bytes of a UTF-8 character that hasn't been completed yet
+    pending: Vec<u8>,
+}
+
+impl fmt::Debug for ByteSource<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ByteSource")
+            .field("pending", &self.pending)
+            .finish_non_exhaustive()
+    }
+}
+
+/// Turns Monkey source into a stream of tokens, one `next_token` call at a time.
+///
+/// ```
+/// use interpreter_rust::lexer::{Lexer, Token};
+///
+/// let mut lexer = Lexer::new("let x = 5;".into());
+///
+/// let mut tokens = vec![];
+/// loop {
+///     let token = lexer.next_token().unwrap();
+///     if token == Token::EOF {
+///         break;
+///     }
+///     tokens.push(token);
+/// }
+///
+/// assert_eq!(
+///     tokens,
+///     vec![
+///         Token::Let,
+///         Token::Ident("x".into()),
+///         Token::Assign,
+///         Token::Int("5".into()),
+///         Token::Semicolon,
+///     ]
+/// );
+/// ```
 #[derive(Debug)]
-pub struct Lexer {
+pub struct Lexer<'a> {
     input: String,
+    source: Option<ByteSource<'a>>,
     position: usize,
     read_position: usize,
     ch: u8,
+    config: LexerConfig,
+    blank_line_before: bool,
+    // End of the previous token, to check that tokens never overlap
+    last_end: usize,
+    // Start of the token or block comment being read, where a lexer error's span begins
+    failed_start: usize,
+    // Copy of every token lexed since record_tokens, up to the first EOF
+    recorded: Option<Vec<(Token, Span)>>,
 }
 
-impl Lexer {
-    pub fn new(source: String) -> Lexer {
+impl<'a> Lexer<'a> {
+    pub fn new(source: String) -> Lexer<'a> {
+        Lexer::with_config(source, LexerConfig::default())
+    }
+
+    pub fn with_config(source: String, config: LexerConfig) -> Lexer<'a> {
         let mut lexer = Lexer {
             input: source,
+            source: None,
             position: 0,
             read_position: 0,
             ch: 0,
+            config,
+            blank_line_before: false,
+            last_end: 0,
+            failed_start: 0,
+            recorded: None,
         };
 
         lexer.read_char();
@@ -98,15 +378,36 @@ impl Lexer {
     }
 
     pub fn next_token(&mut self) -> Result<Token> {
-        self.skip_whitespace();
+        let (token, _) = self.next_token_with_range()?;
+        Ok(token)
+    }
+
+    /// Lexes the next token along with its byte range in the source
+    pub fn next_spanned(&mut self) -> Result<(Token, Span)> {
+        self.next_token_with_range()
+    }
+
+    /// Whether the most recently lexed token was preceded by at least one blank line
+    pub fn blank_line_before(&self) -> bool {
+        self.blank_line_before
+    }
+
+    fn read_token(&mut self) -> Result<RawToken> {
+        // A digit separator can't start a number
+        if self.ch == b'_' && self.peek_char().is_ascii_digit() {
+            return Err(self.malformed_number(self.position));
+        }
 
         let token = match self.ch {
-            b'a'..=b'z' | b'A'..=b'Z' | b'_' => {
-                let ident = self.read_ident();
-                return Ok(self.lookup_ident(&ident));
+            b'a'..=b'z' | b'A'..=b'Z' | b'_' | 0x80.. if self.at_ident_start() => {
+                let range = self.read_ident_range();
+                return match self.lookup_keyword(&self.input[range.clone()]) {
+                    Some(keyword) => Ok(RawToken::Other(keyword)),
+                    None => Ok(RawToken::Ident(range)),
+                };
             }
             b'0'..=b'9' => {
-                return Ok(Token::Int(self.read_number()));
+                return self.read_number();
             }
             b'=' => {
                 if self.peek_char() == b'=' {
@@ -128,27 +429,150 @@ impl Lexer {
             }
             b'*' => Token::Asterisk,
             b'/' => Token::Slash,
-            b'<' => Token::LessThan,
-            b'>' => Token::GreaterThan,
+            b'%' => Token::Percent,
+            b'&' => {
+                if self.peek_char() == b'&' {
+                    self.read_char();
+                    Token::And
+                } else {
+                    Token::Illegal('&')
+                }
+            }
+            b'|' => {
+                if self.peek_char() == b'|' {
+                    self.read_char();
+                    Token::Or
+                } else {
+                    Token::Illegal('|')
+                }
+            }
+            b'<' => {
+                if self.peek_char() == b'=' {
+                    self.read_char();
+                    Token::LessEqual
+                } else {
+                    Token::LessThan
+                }
+            }
+            b'>' => {
+                if self.peek_char() == b'=' {
+                    self.read_char();
+                    Token::GreaterEqual
+                } else {
+                    Token::GreaterThan
+                }
+            }
             b',' => Token::Comma,
             b';' => Token::Semicolon,
+            b':' => Token::Colon,
+            b'.' => Token::Dot,
             b'(' => Token::LParen,
             b')' => Token::RParen,
             b'{' => Token::LBrace,
             b'}' => Token::RBrace,
+            b'[' => Token::LBracket,
+            b']' => Token::RBracket,
+            b'"' => Token::String(self.read_string()?),
             0 => Token::EOF,
-            _ => unreachable!(
-                "Unexpected token, got {:?} ({:?})",
-                self.ch,
-                String::from_utf8_lossy(&[self.ch])
-            ),
+            _ => {
+                let ch = self.current_char();
+                // Step over every byte of a multi-byte character
+                for _ in 1..ch.len_utf8() {
+                    self.read_char();
+                }
+                Token::Illegal(ch)
+            }
         };
 
         self.read_char();
-        Ok(token)
+        Ok(RawToken::Other(token))
+    }
+
+    /// Creates a lexer that pulls its input from `bytes` as tokens are read, instead of
+    /// needing the whole source up front. Invalid UTF-8 is replaced with U+FFFD.
+    pub fn from_bytes(bytes: impl Iterator<Item = u8> + 'a) -> Lexer<'a> {
+        Lexer::from_bytes_with_config(bytes, LexerConfig::default())
+    }
+
+    pub fn from_bytes_with_config(
+        bytes: impl Iterator<Item = u8> + 'a,
+        config: LexerConfig,
+    ) -> Lexer<'a> {
+        let mut lexer = Lexer::with_config(String::new(), config);
+        lexer.source = Some(ByteSource {
+            bytes: Box::new(bytes),
+            pending: vec![],
+        });
+
+        // with_config read the first char before the source was attached
+        lexer.read_position = 0;
+        lexer.read_char();
+
+        lexer
+    }
+
+    /// Like `next_token`, but identifier and integer payloads borrow from the input instead of
+    /// allocating a `String` per token.
+    pub fn next_token_borrowed(&mut self) -> Result<BorrowedToken<'_>> {
+        let (token, _) = self.next_raw_token()?;
+        Ok(token.borrow(&self.input))
+    }
+
+    /// Returns the part of the input that has not been consumed yet, starting at the
+    /// current character. For a lexer created with `from_bytes` this only covers the bytes
+    /// pulled from the source so far.
+    pub fn remaining(&self) -> &str {
+        self.input.get(self.position..).unwrap_or_default()
+    }
+
+    fn next_token_with_range(&mut self) -> Result<(Token, Range<usize>)> {
+        let (token, range) = self.next_raw_token()?;
+        Ok((token.into_token(&self.input), range))
+    }
+
+    /// Reads the next token for every public entry point, checking that tokens move forward and
+    /// recording it if asked to
+    fn next_raw_token(&mut self) -> Result<(RawToken, Range<usize>)> {
+        self.skip_whitespace()?;
+        let start = self.position.min(self.input.len());
+        self.failed_start = start;
+        let token = self.read_token()?;
+        let end = self.position.min(self.input.len());
+
+        // Catch multi-char or comment handling that steps backwards or stalls
+        debug_assert!(
+            start >= self.last_end,
+            "{:?} starts at {} before the previous token ended at {}",
+            token,
+            start,
+            self.last_end
+        );
+        let eof = matches!(token, RawToken::Other(Token::EOF));
+        debug_assert!(end > start || eof, "{:?} at {} is zero-width", token, start);
+        self.last_end = end;
+
+        if let Some(recorded) = self.recorded.as_mut() {
+            if recorded.last().is_none_or(|(last, _)| *last != Token::EOF) {
+                recorded.push((token.to_token(&self.input), start..end));
+            }
+        }
+
+        Ok((token, start..end))
+    }
+
+    /// Starts keeping a copy of every token lexed from now on, with its span, so a consumer
+    /// like the parser can hand the token stream back without lexing twice
+    pub fn record_tokens(&mut self) {
+        self.recorded = Some(vec![]);
+    }
+
+    /// Returns the tokens recorded since `record_tokens` and stops recording
+    pub fn take_recorded(&mut self) -> Vec<(Token, Span)> {
+        self.recorded.take().unwrap_or_default()
     }
 
     fn read_char(&mut self) {
+        self.fill(self.read_position);
         self.ch = if self.read_position >= self.input.len() {
             0
         } else {
@@ -159,7 +583,8 @@ impl Lexer {
         self.read_position += 1;
     }
 
-    fn peek_char(&self) -> u8 {
+    fn peek_char(&mut self) -> u8 {
+        self.fill(self.read_position);
         if self.read_position >= self.input.len() {
             0
         } else {
@@ -167,25 +592,209 @@ impl Lexer {
         }
     }
 
-    fn read_ident(&mut self) -> String {
+    /// Pulls bytes from a streaming source until the input holds the byte at `idx` or the
+    /// source runs out. Bytes are only appended once they complete a UTF-8 character.
+    fn fill(&mut self, idx: usize) {
+        let Some(source) = self.source.as_mut() else {
+            return;
+        };
+
+        while self.input.len() <= idx {
+            let Some(byte) = source.bytes.next() else {
+                if !source.pending.is_empty() {
+                    self.input.push(char::REPLACEMENT_CHARACTER);
+                }
+                self.source = None;
+                return;
+            };
+            source.pending.push(byte);
+
+            loop {
+                match std::str::from_utf8(&source.pending) {
+                    Ok(s) => {
+                        self.input.push_str(s);
+                        source.pending.clear();
+                        break;
+                    }
+                    Err(err) => match err.error_len() {
+                        Some(len) => {
+                            self.input.push(char::REPLACEMENT_CHARACTER);
+                            source.pending.drain(..err.valid_up_to() + len);
+                        }
+                        // Incomplete character, wait for more bytes
+                        None => break,
+                    },
+                }
+            }
+        }
+    }
+
+    /// Reads an identifier made of Unicode alphabetic characters and `_`. ASCII input stays on
+    /// the byte fast path; only bytes of multi-byte characters are decoded into a `char`.
+    fn read_ident_range(&mut self) -> Range<usize> {
+        let pos = self.position;
+        loop {
+            if self.ch.is_ascii_alphabetic() || self.ch == b'_' {
+                self.read_char();
+            } else if self.ch >= 0x80 && self.current_char().is_alphabetic() {
+                for _ in 0..self.current_char().len_utf8() {
+                    self.read_char();
+                }
+            } else {
+                break;
+            }
+        }
+        pos..self.position
+    }
+
+    fn at_ident_start(&self) -> bool {
+        self.ch < 0x80 || self.current_char().is_alphabetic()
+    }
+
+    /// Decodes the character starting at the current byte
+    fn current_char(&self) -> char {
+        self.input
+            .get(self.position..)
+            .and_then(|rest| rest.chars().next())
+            .unwrap_or(char::REPLACEMENT_CHARACTER)
+    }
+
+    /// Reads a string literal starting at the opening quote, leaving the lexer on the closing
+    /// one. Returns the contents without the quotes and with escape sequences (`\n`, `\t`,
+    /// `\r`, `\"` and `\\`) resolved. Any other escape is an error, reported once the whole
+    /// literal has been consumed so lexing resumes after its closing quote.
+    fn read_string(&mut self) -> Result<String> {
+        let mut bytes = vec![];
+        let mut unknown_escape = None;
+        loop {
+            self.read_char();
+            match self.ch {
+                b'"' | 0 => break,
+                b'\\' => {
+                    self.read_char();
+                    let byte = match self.ch {
+                        b'n' => b'\n',
+                        b't' => b'\t',
+                        b'r' => b'\r',
+                        b'"' => b'"',
+                        b'\\' => b'\\',
+                        0 => break,
+                        _ => {
+                            unknown_escape.get_or_insert(self.current_char());
+                            continue;
+                        }
+                    };
+                    bytes.push(byte);
+                }
+                byte => bytes.push(byte),
+            }
+        }
+
+        if let Some(ch) = unknown_escape {
+            // read_token doesn't step over the closing quote when lexing fails
+            if self.ch == b'"' {
+                self.read_char();
+            }
+            bail!(LexerError::UnknownEscape(ch))
+        }
+        if self.ch == 0 {
+            bail!(LexerError::UnterminatedString)
+        }
+        Ok(String::from_utf8_lossy(&bytes).into_owned())
+    }
+
+    fn read_number(&mut self) -> Result<RawToken> {
+        // Prefixed literals are normalized, so there is no source slice to borrow
+        if self.at_radix_prefix() {
+            return Ok(RawToken::Other(self.read_radix_number()?));
+        }
+
+        let range = self.read_number_range()?;
+        let number = &self.input[range.clone()];
+        if number.contains('_') {
+            // Separators are stripped, so the literal no longer matches the source
+            Ok(RawToken::Other(number_token(number.replace('_', ""))))
+        } else if number.contains('.') {
+            Ok(RawToken::Float(range))
+        } else {
+            Ok(RawToken::Int(range))
+        }
+    }
+
+    fn at_radix_prefix(&mut self) -> bool {
+        self.ch == b'0' && matches!(self.peek_char(), b'x' | b'X' | b'b' | b'B')
+    }
+
+    /// Reads a `0x` hexadecimal or `0b` binary literal, where `_` separators may only sit
+    /// between two digits. The resulting `Token::Int` holds the value in decimal, so later
+    /// stages only ever parse base 10.
+    fn read_radix_number(&mut self) -> Result<Token> {
         let pos = self.position;
-        while self.ch.is_ascii_alphabetic() || self.ch == b'_' {
+        self.read_char();
+        let radix = if self.ch.eq_ignore_ascii_case(&b'x') {
+            16
+        } else {
+            2
+        };
+        self.read_char();
+
+        // Consume every alphanumeric and `_` so `0xZZ`, `0b12` or `0x_1` is reported as a whole
+        let digits = self.position;
+        while self.ch.is_ascii_alphanumeric() || self.ch == b'_' {
             self.read_char();
         }
 
-        return String::from_utf8_lossy(&self.input.as_bytes()[pos..self.position]).to_string();
+        let digits = &self.input[digits..self.position];
+        let separators_ok =
+            !digits.starts_with('_') && !digits.ends_with('_') && !digits.contains("__");
+        let literal = self.input[pos..self.position].to_string();
+        match i64::from_str_radix(&digits.replace('_', ""), radix) {
+            Ok(value) if separators_ok => Ok(Token::Int(value.to_string())),
+            // Well-formed but too large, like a decimal literal the parser can't fit in an i64
+            Err(err) if separators_ok && *err.kind() == IntErrorKind::PosOverflow => {
+                bail!(LexerError::IntegerOverflow(literal))
+            }
+            _ => bail!(LexerError::MalformedNumber(literal)),
+        }
     }
 
-    fn read_number(&mut self) -> String {
+    /// Reads the digits of a number, plus a fractional part if a `.` is followed by a digit.
+    /// A trailing `.` without digits is left for the next token. The range still includes any
+    /// `_` digit separators.
+    fn read_number_range(&mut self) -> Result<Range<usize>> {
         let pos = self.position;
-        while self.ch.is_ascii_digit() {
-            self.read_char()
+        self.read_digits(pos)?;
+        if self.ch == b'.' && self.peek_char().is_ascii_digit() {
+            self.read_char();
+            self.read_digits(pos)?;
+        }
+        Ok(pos..self.position)
+    }
+
+    /// Reads a run of digits where a `_` separator may only sit between two digits
+    fn read_digits(&mut self, start: usize) -> Result<()> {
+        while self.ch.is_ascii_digit() || self.ch == b'_' {
+            if self.ch == b'_' && !self.peek_char().is_ascii_digit() {
+                return Err(self.malformed_number(start));
+            }
+            self.read_char();
+        }
+        Ok(())
+    }
+
+    /// Consumes the rest of a malformed number literal starting at `start`, so the error
+    /// reports it whole
+    fn malformed_number(&mut self, start: usize) -> Error {
+        while self.ch.is_ascii_alphanumeric() || self.ch == b'_' {
+            self.read_char();
         }
-        return String::from_utf8_lossy(&self.input.as_bytes()[pos..self.position]).to_string();
+        anyhow!(LexerError::MalformedNumber(
+            self.input[start..self.position].to_string()
+        ))
     }
 
-    fn lookup_ident(&self, ident: &str) -> Token {
-        match ident {
+    fn lookup_keyword(&self, ident: &str) -> Option<Token> {
+        let token = match ident {
             "fn" => Token::Function,
             "let" => Token::Let,
             "true" => Token::True,
@@ -193,21 +802,153 @@ impl Lexer {
             "if" => Token::If,
             "else" => Token::Else,
             "return" => Token::Return,
-            _ => Token::Ident(ident.to_string()),
+            "and" if self.config.word_operators => Token::And,
+            "or" if self.config.word_operators => Token::Or,
+            "not" if self.config.word_operators => Token::Bang,
+            _ => return None,
+        };
+        Some(token)
+    }
+
+    /// Skips whitespace and comments, noting whether they contained a blank line
+    fn skip_whitespace(&mut self) -> Result<()> {
+        // Newlines seen since the last non-whitespace, a comment line is not blank
+        let mut newlines = 0;
+        self.blank_line_before = false;
+        loop {
+            if self.ch.is_ascii_whitespace() {
+                if self.ch == b'\n' {
+                    newlines += 1;
+                    self.blank_line_before |= newlines > 1;
+                }
+                self.read_char();
+            } else if self.ch == b'/' && self.peek_char() == b'/' {
+                self.skip_line_comment();
+                newlines = 0;
+            } else if self.ch == b'/' && self.peek_char() == b'*' {
+                self.failed_start = self.position;
+                self.skip_block_comment()?;
+                newlines = 0;
+            } else {
+                break;
+            }
+        }
+        Ok(())
+    }
+
+    /// Skips a `//` comment up to, but not including, the newline that ends it
+    fn skip_line_comment(&mut self) {
+        while self.ch != b'\n' && self.ch != 0 {
+            self.read_char();
         }
     }
 
-    fn skip_whitespace(&mut self) {
-        while self.ch.is_ascii_whitespace() {
+    /// Skips a `/* ... */` comment, including the terminator. Block comments don't nest.
+    fn skip_block_comment(&mut self) -> Result<()> {
+        // Step over the opening `/*`
+        self.read_char();
+        self.read_char();
+        while !(self.ch == b'*' && self.peek_char() == b'/') {
+            if self.ch == 0 {
+                bail!(LexerError::UnterminatedComment)
+            }
             self.read_char();
         }
+        self.read_char();
+        self.read_char();
+        Ok(())
     }
 }
 
 #[cfg(test)]
 mod test {
-    use super::{Lexer, Token};
+    use super::{
+        highlight_tokens, BorrowedToken, Lexer, LexerConfig, LexerError, Token, TokenCategory,
+    };
     use anyhow::{Ok, Result};
+    use std::{cell::Cell, rc::Rc};
+
+    const COMPLEX_INPUT: &str = r#"let five = 5;
+        let ten = 10;
+        let add = fn(x, y) {
+            x + y;
+        };
+        let result = add(five, ten);
+            !-/ *5;
+            5 < 10 > 5;
+            if (5 < 10) {
+                return true;
+            } else {
+                return false;
+            }
+
+            10 == 10;
+            10 != 9;
+    "#;
+
+    /// Renders the full token stream of `source`, one token per line, for snapshot assertions
+    fn tokens_snapshot(source: &str) -> String {
+        let mut lexer = Lexer::new(source.into());
+        let mut lines = vec![];
+
+        loop {
+            let token = lexer.next_token().unwrap();
+            lines.push(token.to_string());
+            if token == Token::EOF {
+                break;
+            }
+        }
+
+        lines.join("\n")
+    }
+
+    /// Lexes all of `source` and asserts every token starts at or after the end of the previous
+    /// one, and that only `EOF` is zero-width
+    fn assert_lexer_progress(source: &str) {
+        let mut lexer = Lexer::new(source.into());
+        let mut last_end = 0;
+
+        loop {
+            let (token, range) = lexer.next_token_with_range().unwrap();
+            assert!(
+                range.start >= last_end,
+                "{:?} at {:?} overlaps the previous token ending at {}",
+                token,
+                range,
+                last_end
+            );
+            if token == Token::EOF {
+                assert_eq!(range.start, range.end);
+                break;
+            }
+            assert!(range.end > range.start, "{:?} is zero-width", token);
+            last_end = range.end;
+        }
+    }
+
+    /// Describes the first token at which `a` and `b` lex differently, or `None` if the token
+    /// streams are identical
+    fn diff_tokens(a: &str, b: &str) -> Option<String> {
+        let mut left = Lexer::new(a.into());
+        let mut right = Lexer::new(b.into());
+
+        for index in 0.. {
+            let (left_token, left_range) = left.next_token_with_range().unwrap();
+            let (right_token, right_range) = right.next_token_with_range().unwrap();
+
+            if left_token != right_token {
+                return Some(format!(
+                    "token {} differs: {} at {:?} vs {} at {:?}",
+                    index, left_token, left_range, right_token, right_range
+                ));
+            }
+            if left_token == Token::EOF {
+                break;
+            }
+        }
+
+        None
+    }
 
     #[test]
     fn get_next_token() -> Result<()> {
@@ -236,23 +977,7 @@ mod test {
 
     #[test]
     fn get_next_token_complex() -> Result<()> {
-        let input = r#"let five = 5;
-        let ten = 10;
-        let add = fn(x, y) {
-            x + y;
-        };
-        let result = add(five, ten);
-            !-/*5;
-            5 < 10 > 5;
-            if (5 < 10) {
-                return true;
-            } else {
-                return false;
-            }
-
-            10 == 10;
-            10 != 9;
-    "#;
+        let input = COMPLEX_INPUT;
 
         let mut lexer = Lexer::new(input.into());
 
@@ -341,4 +1066,989 @@ mod test {
 
         Ok(())
     }
+
+    #[test]
+    fn highlight_token_ranges() -> Result<()> {
+        let input = "let x = 5;";
+
+        let expected = vec![
+            (TokenCategory::Keyword, 0..3),
+            (TokenCategory::Identifier, 4..5),
+            (TokenCategory::Operator, 6..7),
+            (TokenCategory::Literal, 8..9),
+            (TokenCategory::Delimiter, 9..10),
+        ];
+
+        assert_eq!(highlight_tokens(input), expected);
+
+        Ok(())
+    }
+
+    #[test]
+    fn highlight_token_ranges_multi_char() -> Result<()> {
+        let input = "  foo == 10 ";
+
+        let expected = vec![
+            (TokenCategory::Identifier, 2..5),
+            (TokenCategory::Operator, 6..8),
+            (TokenCategory::Literal, 9..11),
+        ];
+
+        let tokens = highlight_tokens(input);
+        assert_eq!(tokens, expected);
+        assert_eq!(&input[tokens[1].1.clone()], "==");
+
+        Ok(())
+    }
+
+    #[test]
+    fn highlight_tokens_past_errors() -> Result<()> {
+        let input = "x 0xZZ y /* open";
+
+        let expected = vec![
+            (TokenCategory::Identifier, 0..1),
+            (TokenCategory::Illegal, 2..6),
+            (TokenCategory::Identifier, 7..8),
+            (TokenCategory::Illegal, 9..16),
+        ];
+        assert_eq!(highlight_tokens(input), expected);
+
+        // An unclosed string runs to the end of the input
+        let input = "x \"unterminated y z";
+        let expected = vec![
+            (TokenCategory::Identifier, 0..1),
+            (TokenCategory::Illegal, 2..input.len()),
+        ];
+        assert_eq!(highlight_tokens(input), expected);
+
+        Ok(())
+    }
+
+    #[test]
+    fn snapshot_simple() -> Result<()> {
+        assert_eq!(
+            tokens_snapshot("let x = 5;"),
+            "let\nIdent(x)\n=\nInt(5)\n;\nEOF"
+        );
+        assert_eq!(tokens_snapshot(""), "EOF");
+
+        Ok(())
+    }
+
+    #[test]
+    fn snapshot_complex() -> Result<()> {
+        let expected = "\
+let
+Ident(five)
+=
+Int(5)
+;
+let
+Ident(ten)
+=
+Int(10)
+;
+let
+Ident(add)
+=
+fn
+(
+Ident(x)
+,
+Ident(y)
+)
+{
+Ident(x)
++
+Ident(y)
+;
+}
+;
+let
+Ident(result)
+=
+Ident(add)
+(
+Ident(five)
+,
+Ident(ten)
+)
+;
+!
+-
+/
+*
+Int(5)
+;
+Int(5)
+<
+Int(10)
+>
+Int(5)
+;
+if
+(
+Int(5)
+<
+Int(10)
+)
+{
+return
+true
+;
+}
+else
+{
+return
+false
+;
+}
+Int(10)
+==
+Int(10)
+;
+Int(10)
+!=
+Int(9)
+;
+EOF";
+
+        assert_eq!(tokens_snapshot(COMPLEX_INPUT), expected);
+
+        Ok(())
+    }
+
+    #[test]
+    fn word_operators() -> Result<()> {
+        let config = LexerConfig {
+            word_operators: true,
+        };
+        let mut lexer = Lexer::with_config("a and b or not c".into(), config);
+
+        let tokens = vec![
+            Token::Ident("a".into()),
+            Token::And,
+            Token::Ident("b".into()),
+            Token::Or,
+            Token::Bang,
+            Token::Ident("c".into()),
+            Token::EOF,
+        ];
+
+        for token in tokens {
+            assert_eq!(token, lexer.next_token()?)
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn word_operators_disabled_by_default() -> Result<()> {
+        let mut lexer = Lexer::new("a and b".into());
+
+        let tokens = vec![
+            Token::Ident("a".into()),
+            Token::Ident("and".into()),
+            Token::Ident("b".into()),
+            Token::EOF,
+        ];
+
+        for token in tokens {
+            assert_eq!(token, lexer.next_token()?)
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn remaining_input() -> Result<()> {
+        let mut lexer = Lexer::new("let x = 5;".into());
+        assert_eq!(lexer.remaining(), "let x = 5;");
+
+        lexer.next_token()?;
+        lexer.next_token()?;
+        assert_eq!(lexer.remaining(), " = 5;");
+
+        while lexer.next_token()? != Token::EOF {}
+        assert_eq!(lexer.remaining(), "");
+
+        Ok(())
+    }
+
+    #[test]
+    fn borrowed_tokens_match_owned() -> Result<()> {
+        let mut owned = Lexer::new(COMPLEX_INPUT.into());
+        let mut borrowed = Lexer::new(COMPLEX_INPUT.into());
+
+        loop {
+            let token = owned.next_token()?;
+            assert_eq!(borrowed.next_token_borrowed()?.to_token(), token);
+            if token == Token::EOF {
+                break;
+            }
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn borrowed_token_payloads() -> Result<()> {
+        let mut lexer = Lexer::new("let five = 5;".into());
+
+        assert_eq!(
+            lexer.next_token_borrowed()?,
+            BorrowedToken::Other(Token::Let)
+        );
+        assert_eq!(lexer.next_token_borrowed()?, BorrowedToken::Ident("five"));
+        assert_eq!(
+            lexer.next_token_borrowed()?,
+            BorrowedToken::Other(Token::Assign)
+        );
+        assert_eq!(lexer.next_token_borrowed()?, BorrowedToken::Int("5"));
+
+        Ok(())
+    }
+
+    #[test]
+    fn diff_token_streams() -> Result<()> {
+        assert_eq!(diff_tokens(COMPLEX_INPUT, COMPLEX_INPUT), None);
+        assert_eq!(diff_tokens("let x = 5;", "let   x=5;"), None);
+
+        assert_eq!(
+            diff_tokens("let x = 1 + 2;", "let x = 1 - 2;"),
+            Some("token 4 differs: + at 10..11 vs - at 10..11".into())
+        );
+        assert_eq!(
+            diff_tokens("x == y", "x = y"),
+            Some("token 1 differs: == at 2..4 vs = at 2..3".into())
+        );
+        assert_eq!(
+            diff_tokens("x;", "x"),
+            Some("token 1 differs: ; at 1..2 vs EOF at 1..1".into())
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn blank_line_before_token() -> Result<()> {
+        let mut lexer = Lexer::new("let x = 5;\n\nx;\n  \n\ty\nz".into());
+
+        let tokens = [
+            (Token::Let, false),
+            (Token::Ident("x".into()), false),
+            (Token::Assign, false),
+            (Token::Int("5".into()), false),
+            (Token::Semicolon, false),
+            (Token::Ident("x".into()), true),
+            (Token::Semicolon, false),
+            (Token::Ident("y".into()), true),
+            (Token::Ident("z".into()), false),
+        ];
+
+        for (token, blank_line) in tokens {
+            assert_eq!(lexer.next_token()?, token);
+            assert_eq!(lexer.blank_line_before(), blank_line);
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn from_chunked_bytes() -> Result<()> {
+        let chunks = COMPLEX_INPUT.as_bytes().chunks(7);
+        let mut streaming = Lexer::from_bytes(chunks.flat_map(|chunk| chunk.iter().copied()));
+        let mut lexer = Lexer::new(COMPLEX_INPUT.into());
+
+        loop {
+            let token = lexer.next_token()?;
+            assert_eq!(streaming.next_token()?, token);
+            if token == Token::EOF {
+                break;
+            }
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn from_bytes_reads_lazily() -> Result<()> {
+        let pulled = Rc::new(Cell::new(0));
+        let counter = pulled.clone();
+        let bytes = "let x = 5;"
+            .bytes()
+            .inspect(move |_| counter.set(counter.get() + 1));
+        let mut lexer = Lexer::from_bytes(bytes);
+
+        assert_eq!(lexer.next_token()?, Token::Let);
+        assert_eq!(pulled.get(), 4);
+        assert_eq!(lexer.remaining(), " ");
+
+        assert_eq!(lexer.next_token()?, Token::Ident("x".into()));
+        assert_eq!(lexer.next_token()?, Token::Assign);
+        assert_eq!(lexer.next_token()?, Token::Int("5".into()));
+        assert_eq!(lexer.next_token()?, Token::Semicolon);
+        assert_eq!(lexer.next_token()?, Token::EOF);
+        assert_eq!(pulled.get(), 10);
+
+        Ok(())
+    }
+
+    #[test]
+    fn from_borrowed_bytes_with_config() -> Result<()> {
+        let source = String::from("a and not b");
+        let config = LexerConfig {
+            word_operators: true,
+        };
+        let mut lexer = Lexer::from_bytes_with_config(source.bytes(), config);
+
+        let tokens = vec![
+            Token::Ident("a".into()),
+            Token::And,
+            Token::Bang,
+            Token::Ident("b".into()),
+            Token::EOF,
+        ];
+        for token in tokens {
+            assert_eq!(token, lexer.next_token()?)
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn from_bytes_decodes_utf8() -> Result<()> {
+        let bytes: &'static [u8] = b"a \xC3\xA9 \xFF\xC3A \xE2\x82";
+        let mut lexer = Lexer::from_bytes(bytes.iter().copied());
+
+        lexer.fill(usize::MAX - 1);
+        assert_eq!(lexer.remaining(), "a \u{e9} \u{fffd}\u{fffd}A \u{fffd}");
+
+        Ok(())
+    }
+
+    #[test]
+    fn generated_literal_and_display() -> Result<()> {
+        for token in Token::FIXED {
+            match token {
+                Token::EOF => {
+                    assert_eq!(token.token_literal(), "");
+                    assert_eq!(token.to_string(), format!("{:?}", token));
+                }
+                _ => {
+                    assert!(!token.token_literal().is_empty(), "{:?}", token);
+                    assert_eq!(token.to_string(), token.token_literal());
+                }
+            }
+        }
+
+        assert_eq!(Token::Ident("x".into()).token_literal(), "x");
+        assert_eq!(Token::Ident("x".into()).to_string(), "Ident(x)");
+        assert_eq!(Token::Int("5".into()).to_string(), "Int(5)");
+
+        Ok(())
+    }
+
+    #[test]
+    fn string_literals() -> Result<()> {
+        let input = r#""foobar" "foo bar" "" x"#;
+        let mut lexer = Lexer::new(input.into());
+
+        let tokens = vec![
+            Token::String("foobar".into()),
+            Token::String("foo bar".into()),
+            Token::String("".into()),
+            Token::Ident("x".into()),
+            Token::EOF,
+        ];
+
+        for token in tokens {
+            assert_eq!(token, lexer.next_token()?)
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn unterminated_string() -> Result<()> {
+        let mut lexer = Lexer::new(r#"let s = "foo"#.into());
+
+        lexer.next_token()?;
+        lexer.next_token()?;
+        lexer.next_token()?;
+        let err = lexer.next_token().unwrap_err();
+
+        assert!(matches!(
+            err.downcast_ref::<LexerError>(),
+            Some(LexerError::UnterminatedString)
+        ));
+
+        Ok(())
+    }
+
+    #[test]
+    fn float_literals() -> Result<()> {
+        let input = "1.0 0.5 42 3. 3.14.15";
+        let mut lexer = Lexer::new(input.into());
+
+        let tokens = vec![
+            Token::Float("1.0".into()),
+            Token::Float("0.5".into()),
+            Token::Int("42".into()),
+            Token::Int("3".into()),
+            Token::Dot,
+            Token::Float("3.14".into()),
+            Token::Dot,
+            Token::Int("15".into()),
+            Token::EOF,
+        ];
+
+        for token in tokens {
+            assert_eq!(token, lexer.next_token()?)
+        }
+
+        let mut lexer = Lexer::new("2.5".into());
+        assert_eq!(lexer.next_token_borrowed()?, BorrowedToken::Float("2.5"));
+
+        Ok(())
+    }
+
+    #[test]
+    fn line_comments() -> Result<()> {
+        let input = r#"// full line comment
+        let x = 5; // trailing comment
+        x / 2;
+        //"#;
+        let mut lexer = Lexer::new(input.into());
+
+        let tokens = vec![
+            Token::Let,
+            Token::Ident("x".into()),
+            Token::Assign,
+            Token::Int("5".into()),
+            Token::Semicolon,
+            Token::Ident("x".into()),
+            Token::Slash,
+            Token::Int("2".into()),
+            Token::Semicolon,
+            Token::EOF,
+        ];
+
+        for token in tokens {
+            assert_eq!(token, lexer.next_token()?)
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn comment_line_is_not_blank() -> Result<()> {
+        let mut lexer = Lexer::new("x;\n// comment\ny;\n// comment\n\nz".into());
+
+        lexer.next_token()?;
+        lexer.next_token()?;
+        assert_eq!(lexer.next_token()?, Token::Ident("y".into()));
+        assert!(!lexer.blank_line_before());
+
+        lexer.next_token()?;
+        assert_eq!(lexer.next_token()?, Token::Ident("z".into()));
+        assert!(lexer.blank_line_before());
+
+        Ok(())
+    }
+
+    #[test]
+    fn block_comments() -> Result<()> {
+        let input = r#"5 /* ignore */ + 5;
+        /* a comment
+           spanning * several / lines
+        */
+        x/**/;"#;
+        let mut lexer = Lexer::new(input.into());
+
+        let tokens = vec![
+            Token::Int("5".into()),
+            Token::Plus,
+            Token::Int("5".into()),
+            Token::Semicolon,
+            Token::Ident("x".into()),
+            Token::Semicolon,
+            Token::EOF,
+        ];
+
+        for token in tokens {
+            assert_eq!(token, lexer.next_token()?)
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn unterminated_block_comment() -> Result<()> {
+        let mut lexer = Lexer::new("x /* never closed *".into());
+
+        assert_eq!(lexer.next_token()?, Token::Ident("x".into()));
+        let err = lexer.next_token().unwrap_err();
+
+        assert!(matches!(
+            err.downcast_ref::<LexerError>(),
+            Some(LexerError::UnterminatedComment)
+        ));
+
+        Ok(())
+    }
+
+    #[test]
+    fn comparison_tokens() -> Result<()> {
+        let mut lexer = Lexer::new("5 <= 10 >= 5 < 1 > 2".into());
+
+        let tokens = vec![
+            Token::Int("5".into()),
+            Token::LessEqual,
+            Token::Int("10".into()),
+            Token::GreaterEqual,
+            Token::Int("5".into()),
+            Token::LessThan,
+            Token::Int("1".into()),
+            Token::GreaterThan,
+            Token::Int("2".into()),
+            Token::EOF,
+        ];
+
+        for token in tokens {
+            assert_eq!(token, lexer.next_token()?)
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn modulo_token() -> Result<()> {
+        let mut lexer = Lexer::new("10 % 3".into());
+
+        let tokens = vec![
+            Token::Int("10".into()),
+            Token::Percent,
+            Token::Int("3".into()),
+            Token::EOF,
+        ];
+
+        for token in tokens {
+            assert_eq!(token, lexer.next_token()?)
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn token_categories() -> Result<()> {
+        let cases = [
+            (Token::Let, TokenCategory::Keyword),
+            (Token::Ident("x".into()), TokenCategory::Identifier),
+            (Token::Int("5".into()), TokenCategory::Literal),
+            (Token::String("s".into()), TokenCategory::Literal),
+            (Token::True, TokenCategory::Literal),
+            (Token::Plus, TokenCategory::Operator),
+            (Token::LessEqual, TokenCategory::Operator),
+            (Token::Semicolon, TokenCategory::Delimiter),
+            (Token::LBrace, TokenCategory::Delimiter),
+            (Token::EOF, TokenCategory::EndOfFile),
+            (Token::Illegal('@'), TokenCategory::Illegal),
+        ];
+
+        for (token, category) in cases {
+            assert_eq!(token.category(), category, "{:?}", token);
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn logical_tokens() -> Result<()> {
+        let mut lexer = Lexer::new("true && false || true & |".into());
+
+        let tokens = vec![
+            Token::True,
+            Token::And,
+            Token::False,
+            Token::Or,
+            Token::True,
+            Token::Illegal('&'),
+            Token::Illegal('|'),
+            Token::EOF,
+        ];
+
+        for token in tokens {
+            assert_eq!(token, lexer.next_token()?)
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn string_escapes() -> Result<()> {
+        let input = r#""a\tb" "\"" "line1\nline2\r" "back\\slash" "é\n""#;
+        let mut lexer = Lexer::new(input.into());
+
+        let tokens = vec![
+            Token::String("a\tb".into()),
+            Token::String("\"".into()),
+            Token::String("line1\nline2\r".into()),
+            Token::String("back\\slash".into()),
+            Token::String("é\n".into()),
+            Token::EOF,
+        ];
+
+        for token in tokens {
+            assert_eq!(token, lexer.next_token()?)
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn unknown_string_escape() -> Result<()> {
+        let mut lexer = Lexer::new(r#""\q""#.into());
+        let err = lexer.next_token().unwrap_err();
+
+        assert!(matches!(
+            err.downcast_ref::<LexerError>(),
+            Some(LexerError::UnknownEscape('q'))
+        ));
+
+        // The rest of the literal is skipped, so lexing resumes after the closing quote
+        let mut lexer = Lexer::new(r#"let s = "a\qb\"c"; let y = 2;"#.into());
+        let tokens = vec![Token::Let, Token::Ident("s".into()), Token::Assign];
+        for token in tokens {
+            assert_eq!(token, lexer.next_token()?)
+        }
+        assert!(lexer.next_token().is_err());
+        let tokens = vec![
+            Token::Semicolon,
+            Token::Let,
+            Token::Ident("y".into()),
+            Token::Assign,
+            Token::Int("2".into()),
+            Token::Semicolon,
+            Token::EOF,
+        ];
+        for token in tokens {
+            assert_eq!(token, lexer.next_token()?)
+        }
+
+        // The escaped character is decoded whole, not just its first byte
+        let mut lexer = Lexer::new(r#""\é" x"#.into());
+        let err = lexer.next_token().unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<LexerError>(),
+            Some(LexerError::UnknownEscape('é'))
+        ));
+        assert_eq!(lexer.next_token()?, Token::Ident("x".into()));
+
+        let mut lexer = Lexer::new(r#""abc\"#.into());
+        let err = lexer.next_token().unwrap_err();
+
+        assert!(matches!(
+            err.downcast_ref::<LexerError>(),
+            Some(LexerError::UnterminatedString)
+        ));
+
+        Ok(())
+    }
+
+    #[test]
+    fn hex_and_binary_literals() -> Result<()> {
+        let mut lexer =
+            Lexer::new("0xff 0XFF 0b101 0B0 255 0 0x7fffffffffffffff 0xff_ff 0b1_0".into());
+
+        let tokens = vec![
+            Token::Int("255".into()),
+            Token::Int("255".into()),
+            Token::Int("5".into()),
+            Token::Int("0".into()),
+            Token::Int("255".into()),
+            Token::Int("0".into()),
+            Token::Int(i64::MAX.to_string()),
+            Token::Int("65535".into()),
+            Token::Int("2".into()),
+            Token::EOF,
+        ];
+
+        for token in tokens {
+            assert_eq!(token, lexer.next_token()?)
+        }
+
+        let mut lexer = Lexer::new("0x1f;".into());
+        assert_eq!(
+            lexer.next_token_borrowed()?,
+            BorrowedToken::Other(Token::Int("31".into()))
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn malformed_radix_literals() -> Result<()> {
+        for input in ["0xZZ", "0b102", "0x", "0x_ff", "0xff_", "0b1__0"] {
+            let mut lexer = Lexer::new(input.into());
+            let err = lexer.next_token().unwrap_err();
+
+            match err.downcast_ref::<LexerError>() {
+                Some(LexerError::MalformedNumber(literal)) => assert_eq!(literal, input),
+                _ => panic!("expected MalformedNumber for {}, got {}", input, err),
+            }
+        }
+
+        let mut lexer = Lexer::new("0x8000000000000000 0b1_0".into());
+        let err = lexer.next_token().unwrap_err();
+        match err.downcast_ref::<LexerError>() {
+            Some(LexerError::IntegerOverflow(literal)) => assert_eq!(literal, "0x8000000000000000"),
+            _ => panic!("expected IntegerOverflow, got {}", err),
+        }
+        assert_eq!(
+            err.to_string(),
+            "Integer literal 0x8000000000000000 does not fit in 64 bits"
+        );
+        assert_eq!(lexer.next_token()?, Token::Int("2".into()));
+
+        Ok(())
+    }
+
+    #[test]
+    fn digit_separators() -> Result<()> {
+        let mut lexer = Lexer::new("1_000_000 10_00 3_0.2_5 7".into());
+
+        let tokens = vec![
+            Token::Int("1000000".into()),
+            Token::Int("1000".into()),
+            Token::Float("30.25".into()),
+            Token::Int("7".into()),
+            Token::EOF,
+        ];
+
+        for token in tokens {
+            assert_eq!(token, lexer.next_token()?)
+        }
+
+        let mut lexer = Lexer::new("1_0.5".into());
+        assert_eq!(
+            lexer.next_token_borrowed()?,
+            BorrowedToken::Other(Token::Float("10.5".into()))
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn misplaced_digit_separators() -> Result<()> {
+        for input in ["1__0", "5_", "5_.0", "1.0_", "_5", "_1_000"] {
+            let mut lexer = Lexer::new(input.into());
+            let err = lexer.next_token().unwrap_err();
+
+            match err.downcast_ref::<LexerError>() {
+                Some(LexerError::MalformedNumber(_)) => {}
+                _ => panic!("expected MalformedNumber for {}, got {}", input, err),
+            }
+        }
+
+        // An underscore not followed by a digit still starts an identifier
+        let mut lexer = Lexer::new("_x 5".into());
+        assert_eq!(lexer.next_token()?, Token::Ident("_x".into()));
+        assert_eq!(lexer.next_token()?, Token::Int("5".into()));
+
+        Ok(())
+    }
+
+    #[test]
+    fn bracket_tokens() -> Result<()> {
+        let mut lexer = Lexer::new("[1, 2]".into());
+
+        let tokens = vec![
+            Token::LBracket,
+            Token::Int("1".into()),
+            Token::Comma,
+            Token::Int("2".into()),
+            Token::RBracket,
+            Token::EOF,
+        ];
+
+        for token in tokens {
+            assert_eq!(token, lexer.next_token()?)
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn colon_token() -> Result<()> {
+        let mut lexer = Lexer::new(r#"{ "a": 1 }"#.into());
+
+        let tokens = vec![
+            Token::LBrace,
+            Token::String("a".into()),
+            Token::Colon,
+            Token::Int("1".into()),
+            Token::RBrace,
+            Token::EOF,
+        ];
+
+        for token in tokens {
+            assert_eq!(token, lexer.next_token()?)
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn illegal_characters() -> Result<()> {
+        let mut lexer = Lexer::new("@".into());
+        assert_eq!(lexer.next_token()?, Token::Illegal('@'));
+        assert_eq!(lexer.next_token()?, Token::EOF);
+
+        let mut lexer = Lexer::new("x ~ € # y".into());
+
+        let tokens = vec![
+            Token::Ident("x".into()),
+            Token::Illegal('~'),
+            Token::Illegal('€'),
+            Token::Illegal('#'),
+            Token::Ident("y".into()),
+            Token::EOF,
+        ];
+
+        for token in tokens {
+            assert_eq!(token, lexer.next_token()?)
+        }
+
+        assert_eq!(Token::Illegal('@').to_string(), "Illegal(@)");
+        assert_eq!(Token::Illegal('@').token_literal(), "");
+
+        Ok(())
+    }
+
+    #[test]
+    fn lexer_makes_progress() -> Result<()> {
+        let inputs = [
+            COMPLEX_INPUT,
+            "=+(){},;",
+            "",
+            "   ",
+            "let x = 5;\n\nx;",
+            r#""foobar" "foo bar" "" x"#,
+            r#""a\tb" "\"" "é\n""#,
+            "1.0 0.5 42 3. 3.14.15",
+            "0xff 0b101 1_000_000 3_0.2_5",
+            "5 /* ignore */ + 5; // trailing\n/**/x",
+            "5 <= 10 >= 5 % 3 && true || false",
+            r#"[1, 2] { "a": 1 }"#,
+            "x ~ ü # y & |",
+            "let α = 5; let naïve = café;",
+        ];
+
+        for input in inputs {
+            assert_lexer_progress(input);
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn unicode_identifiers() -> Result<()> {
+        let mut lexer = Lexer::new("let α = 5;\nlet naïve = 1;\nдобро_пожаловать + ü".into());
+
+        let tokens = vec![
+            Token::Let,
+            Token::Ident("α".into()),
+            Token::Assign,
+            Token::Int("5".into()),
+            Token::Semicolon,
+            Token::Let,
+            Token::Ident("naïve".into()),
+            Token::Assign,
+            Token::Int("1".into()),
+            Token::Semicolon,
+            Token::Ident("добро_пожаловать".into()),
+            Token::Plus,
+            Token::Ident("ü".into()),
+            Token::EOF,
+        ];
+
+        for token in tokens {
+            assert_eq!(token, lexer.next_token()?)
+        }
+
+        let mut lexer = Lexer::new("café;".into());
+        assert_eq!(lexer.next_token_borrowed()?, BorrowedToken::Ident("café"));
+
+        Ok(())
+    }
+
+    #[test]
+    fn tokenize_with_spans() -> Result<()> {
+        let tokens = super::tokenize("let x = 5;")?;
+
+        let expected = vec![
+            (Token::Let, 0..3),
+            (Token::Ident("x".into()), 4..5),
+            (Token::Assign, 6..7),
+            (Token::Int("5".into()), 8..9),
+            (Token::Semicolon, 9..10),
+            (Token::EOF, 10..10),
+        ];
+        assert_eq!(tokens, expected);
+
+        Ok(())
+    }
+
+    #[test]
+    fn recorded_tokens() -> Result<()> {
+        let mut lexer = Lexer::new("x + y".into());
+        lexer.next_token()?;
+        lexer.record_tokens();
+
+        while lexer.next_token()? != Token::EOF {}
+        lexer.next_token()?;
+
+        let expected = vec![
+            (Token::Plus, 2..3),
+            (Token::Ident("y".into()), 4..5),
+            (Token::EOF, 5..5),
+        ];
+        assert_eq!(lexer.take_recorded(), expected);
+        assert_eq!(lexer.take_recorded(), vec![]);
+
+        // The borrowing path records the same owned tokens
+        let mut lexer = Lexer::new("x + 1_0".into());
+        lexer.record_tokens();
+        while lexer.next_token_borrowed()? != BorrowedToken::Other(Token::EOF) {}
+
+        assert_eq!(lexer.take_recorded(), super::tokenize("x + 1_0")?);
+
+        Ok(())
+    }
+
+    #[test]
+    fn spanned_tokens() -> Result<()> {
+        let mut lexer = Lexer::new("a + b; let xs = 0x1F == 12.5;".into());
+
+        let expected = [
+            (Token::Ident("a".into()), 0..1),
+            (Token::Plus, 2..3),
+            (Token::Ident("b".into()), 4..5),
+            (Token::Semicolon, 5..6),
+            (Token::Let, 7..10),
+            (Token::Ident("xs".into()), 11..13),
+            (Token::Assign, 14..15),
+            (Token::Int("31".into()), 16..20),
+            (Token::Equal, 21..23),
+            (Token::Float("12.5".into()), 24..28),
+            (Token::Semicolon, 28..29),
+            (Token::EOF, 29..29),
+        ];
+        for spanned in expected {
+            assert_eq!(spanned, lexer.next_spanned()?);
+        }
+
+        Ok(())
+    }
 }
@@ -0,0 +1,5 @@
+#![allow(dead_code)]
+pub mod ast;
+pub mod lexer;
+pub mod parser;
+pub mod repl;
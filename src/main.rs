@@ -1,8 +1,5 @@
-#![allow(dead_code)]
-pub mod ast;
-pub mod lexer;
-pub mod parser;
-pub mod repl;
+use interpreter_rust::repl;
+
 fn main() {
     let user = whoami::username();
     print!("\x1B[2J\x1B[1;1H");
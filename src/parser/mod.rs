@@ -1,12 +1,12 @@
-use std::fmt;
+use std::{collections::VecDeque, fmt};
 
 // src/parser/parser.rs
 use crate::{
     ast::{
-        Expression, ExpressionStatement, Identifier, LetStatement, Program, ReturnStatement,
-        Statement,
+        BlockStatement, Expression, ExpressionStatement, Identifier, LetStatement, Operator,
+        Program, ReturnStatement, Statement,
     },
-    lexer::{Lexer, Token},
+    lexer::{Lexer, LexerError, Span, Token},
 };
 use anyhow::*;
 
@@ -15,6 +15,13 @@ pub enum ParserError {
     UnexpectedToken { want: String, got: String },
     MissingIdentifier(Token),
     PrefixExpressionNotImplemented(Token),
+    IllegalCharacter(char),
+    NoProgress(Token),
+    InvalidInteger(String),
+    NotAnInfixOperator(Token),
+    UnclosedBlock,
+    Lexer(LexerError),
+    Other(String),
 }
 
 impl fmt::Display for ParserError {
@@ -35,57 +42,207 @@ impl fmt::Display for ParserError {
                     token.token_literal()
                 )
             }
+            ParserError::IllegalCharacter(ch) => write!(f, "illegal character '{}'", ch),
+            ParserError::NoProgress(token) => {
+                write!(f, "Parser made no progress at token {:?}", token)
+            }
+            ParserError::InvalidInteger(literal) => {
+                write!(f, "Could not parse {} as an integer", literal)
+            }
+            ParserError::NotAnInfixOperator(token) => {
+                write!(
+                    f,
+                    "Token {} is not an infix operator",
+                    token.token_literal()
+                )
+            }
+            ParserError::UnclosedBlock => write!(f, "Block is missing its closing }}"),
+            ParserError::Lexer(err) => err.fmt(f),
+            ParserError::Other(message) => write!(f, "{}", message),
+        }
+    }
+}
+
+/// Recovers the typed error from an error raised while parsing. `LexerError`s passed through
+/// from the lexer are wrapped, and anything else is kept as its message.
+impl From<Error> for ParserError {
+    fn from(err: Error) -> Self {
+        match err.downcast::<ParserError>() {
+            Result::Ok(err) => err,
+            Err(err) => match err.downcast::<LexerError>() {
+                Result::Ok(err) => ParserError::Lexer(err),
+                Err(err) => ParserError::Other(err.to_string()),
+            },
+        }
+    }
+}
+
+/// Parses `source` into a `Program`
+pub fn parse(source: &str) -> Result<Program> {
+    Parser::new(Lexer::new(source.to_string())).parse_program()
+}
+
+/// Parses `source` and also returns every token with its span, lexing the source only once.
+/// On an error the program holds the statements parsed before it. The rest of the source is
+/// still lexed so the token list covers all of it, skipping over any further lexer errors and
+/// adding them to the returned errors.
+pub fn parse_with_tokens(source: &str) -> (Program, Vec<(Token, Span)>, Vec<ParserError>) {
+    let mut lexer = Lexer::new(source.to_string());
+    lexer.record_tokens();
+
+    let mut parser = Parser::new(lexer);
+    let mut program = Program {
+        statements: vec![],
+        blank_lines: vec![],
+    };
+    let mut errors = vec![];
+
+    if let Err(err) = parser.parse_statements(&mut program) {
+        errors.push(ParserError::from(err));
+        loop {
+            match parser.lexer.next_token() {
+                Result::Ok(Token::EOF) => break,
+                Result::Ok(_) => {}
+                Err(err) => errors.push(ParserError::from(err)),
+            }
         }
     }
+
+    (program, parser.lexer.take_recorded(), errors)
 }
 
 #[derive(Debug, PartialOrd, PartialEq)]
 pub enum OperatorPrecedence {
     Lowest,      // Lowest precedence
     Equals,      // ==
-    LessGreater, // > or <
+    LessGreater, // > or < or >= or <=
     Sum,         // +
-    Product,     // *
+    Product,     // * or / or %
     Prefix,      // -X or !X
     Call,        // myFunction(X)
 }
 
-struct Parser {
-    lexer: Lexer,
+/// Binding power of `token` when it appears in infix position. Tokens that aren't infix
+/// operators are `Lowest`, which ends the Pratt loop in `parse_expression`.
+pub fn token_precedence(token: &Token) -> OperatorPrecedence {
+    match token {
+        Token::Equal | Token::NotEqual => OperatorPrecedence::Equals,
+        Token::LessThan | Token::GreaterThan | Token::LessEqual | Token::GreaterEqual => {
+            OperatorPrecedence::LessGreater
+        }
+        Token::Plus | Token::Minus => OperatorPrecedence::Sum,
+        Token::Asterisk | Token::Slash | Token::Percent => OperatorPrecedence::Product,
+        _ => OperatorPrecedence::Lowest,
+    }
+}
+
+pub struct Parser<'a> {
+    lexer: Lexer<'a>,
     current_token: Token,
     peek_token: Token,
+    // Whether a blank line preceded the current/peek token in the source
+    current_blank_line: bool,
+    peek_blank_line: bool,
+    // Tokens lexed past the peek token by peek_token_n, with their blank line flag
+    lookahead: VecDeque<(Token, bool)>,
+    // Number of tokens consumed so far, used to detect statements that don't advance
+    position: usize,
+    // Lexer error hit while reading the first two tokens in new, returned once parsing starts
+    start_error: Option<Error>,
 }
 
-impl Parser {
-    pub fn new(lexer: Lexer) -> Parser {
+impl<'a> Parser<'a> {
+    pub fn new(lexer: Lexer<'a>) -> Parser<'a> {
         let mut parser = Parser {
             lexer,
-            current_token: Token::Illegal,
-            peek_token: Token::Illegal,
+            current_token: Token::EOF,
+            peek_token: Token::EOF,
+            current_blank_line: false,
+            peek_blank_line: false,
+            lookahead: VecDeque::new(),
+            position: 0,
+            start_error: None,
         };
 
-        parser.next_token().unwrap();
-        parser.next_token().unwrap();
+        parser.start_error = parser.next_token().and_then(|()| parser.next_token()).err();
 
         parser
     }
 
     pub fn next_token(&mut self) -> Result<()> {
-        self.current_token = std::mem::replace(&mut self.peek_token, self.lexer.next_token()?);
+        if self.current_token != Token::EOF {
+            self.position += 1;
+        }
+        let (token, blank_line) = match self.lookahead.pop_front() {
+            Some(buffered) => buffered,
+            None => self.lex_token()?,
+        };
+        self.current_token = std::mem::replace(&mut self.peek_token, token);
+        self.current_blank_line = std::mem::replace(&mut self.peek_blank_line, blank_line);
         Ok(())
     }
 
+    /// Returns the token `n` positions after the current one without consuming anything, so
+    /// `peek_token_n(1)` is the peek token. Tokens past it are buffered from the lexer as needed.
+    pub fn peek_token_n(&mut self, n: usize) -> Result<&Token> {
+        match n {
+            0 => Ok(&self.current_token),
+            1 => Ok(&self.peek_token),
+            _ => {
+                while self.lookahead.len() < n - 1 {
+                    let buffered = self.lex_token()?;
+                    self.lookahead.push_back(buffered);
+                }
+                Ok(&self.lookahead[n - 2].0)
+            }
+        }
+    }
+
+    fn lex_token(&mut self) -> Result<(Token, bool)> {
+        let token = self.lexer.next_token()?;
+        Ok((token, self.lexer.blank_line_before()))
+    }
+
     pub fn parse_program(&mut self) -> Result<Program> {
-        let mut program = Program { statements: vec![] };
+        let mut program = Program {
+            statements: vec![],
+            blank_lines: vec![],
+        };
+
+        self.parse_statements(&mut program)?;
+
+        Ok(program)
+    }
+
+    /// Parses statements into `program` until `EOF`, keeping what was parsed if one fails
+    fn parse_statements(&mut self, program: &mut Program) -> Result<()> {
+        self.parse_statements_with(program, Parser::parse_statement)
+    }
+
+    /// Statement loop of `parse_statements`, taking the statement parser so tests can drive a
+    /// misbehaving one through it
+    fn parse_statements_with(
+        &mut self,
+        program: &mut Program,
+        mut parse_statement: impl FnMut(&mut Self) -> Result<Statement>,
+    ) -> Result<()> {
+        if let Some(err) = self.start_error.take() {
+            return Err(err);
+        }
 
         while self.current_token != Token::EOF {
-            let statement = self.parse_statement()?;
+            let start = self.position;
+            if self.current_blank_line && !program.statements.is_empty() {
+                program.blank_lines.push(program.statements.len());
+            }
+            let statement = parse_statement(self)?;
+            self.ensure_progress(start, &statement)?;
             program.statements.push(statement);
             self.next_token()
-                .context("Error occurred when moving to the next token")?
+                .context("Error occurred when moving to the next token")?;
         }
 
-        Ok(program)
+        Ok(())
     }
 
     fn parse_statement(&mut self) -> Result<Statement> {
@@ -104,7 +261,9 @@ impl Parser {
         self.expect_peek(Token::Assign)?;
         self.next_token()?;
 
-        while !self.current_token_is(Token::Semicolon) {
+        let value = self.parse_expression(OperatorPrecedence::Lowest)?;
+
+        if self.peek_token_is(&Token::Semicolon) {
             self.next_token()?;
         }
 
@@ -114,28 +273,52 @@ impl Parser {
                 token: Token::Ident(identifier.clone()),
                 value: identifier,
             },
-            // TODO: We are skipping the expression for now
-            value: Expression::Identifier(Identifier {
-                token: Token::Int("5".into()),
-                value: "5".into(),
-            }),
+            value,
         })
     }
 
     fn parse_return_statement(&mut self) -> Result<ReturnStatement> {
-        while !self.current_token_is(Token::Semicolon) {
+        // A bare `return;` or `return` at the end of input has no value
+        if self.peek_token_is(&Token::Semicolon) || self.peek_token_is(&Token::EOF) {
+            self.next_token()?;
+            return Ok(ReturnStatement {
+                token: Token::Return,
+                return_value: None,
+            });
+        }
+
+        self.next_token()?;
+
+        let return_value = self.parse_expression(OperatorPrecedence::Lowest)?;
+
+        if self.peek_token_is(&Token::Semicolon) {
             self.next_token()?;
         }
 
         Ok(ReturnStatement {
             token: Token::Return,
-            return_value: Expression::Identifier(Identifier {
-                token: Token::Int("5".into()),
-                value: "5".into(),
-            }),
+            return_value: Some(return_value),
         })
     }
 
+    /// Parses the statements of a block starting at its `{`, leaving the `}` as the current token
+    fn parse_block_statement(&mut self) -> Result<BlockStatement> {
+        let token = self.current_token.clone();
+        let mut statements = vec![];
+
+        self.next_token()?;
+
+        while !self.current_token_is(Token::RBrace) {
+            if self.current_token_is(Token::EOF) {
+                bail!(ParserError::UnclosedBlock)
+            }
+            statements.push(self.parse_statement()?);
+            self.next_token()?;
+        }
+
+        Ok(BlockStatement { token, statements })
+    }
+
     fn parse_expression_statement(&mut self) -> Result<ExpressionStatement> {
         let expression = self.parse_expression(OperatorPrecedence::Lowest)?;
 
@@ -148,15 +331,40 @@ impl Parser {
         })
     }
 
-    fn parse_expression(&self, precedence: OperatorPrecedence) -> Result<Expression> {
-        let left_expression = self.parse_prefix()?;
+    fn parse_expression(&mut self, precedence: OperatorPrecedence) -> Result<Expression> {
+        let mut left_expression = self.parse_prefix()?;
+
+        while !self.peek_token_is(&Token::Semicolon) && precedence < self.peek_precedence() {
+            self.next_token()?;
+            left_expression = self.parse_infix_expression(left_expression)?;
+        }
 
         Ok(left_expression)
     }
 
-    fn parse_prefix(&self) -> Result<Expression> {
+    fn parse_infix_expression(&mut self, left: Expression) -> Result<Expression> {
+        let Some(operator) = Operator::infix(&self.current_token) else {
+            bail!(ParserError::NotAnInfixOperator(self.current_token.clone()))
+        };
+        let precedence = self.current_precedence();
+
+        self.next_token()?;
+        let right = self.parse_expression(precedence)?;
+
+        Ok(Expression::Infix {
+            left: Box::new(left),
+            operator,
+            right: Box::new(right),
+        })
+    }
+
+    fn parse_prefix(&mut self) -> Result<Expression> {
         match self.current_token {
             Token::Ident(_) => Ok(self.parse_identifier()),
+            Token::Int(_) => self.parse_integer_literal(),
+            Token::True | Token::False => Ok(self.parse_boolean()),
+            Token::LParen => self.parse_grouped_expression(),
+            Token::Illegal(ch) => bail!(ParserError::IllegalCharacter(ch)),
             _ => bail!(ParserError::PrefixExpressionNotImplemented(
                 self.current_token.clone()
             )),
@@ -170,13 +378,63 @@ impl Parser {
         })
     }
 
+    fn parse_integer_literal(&self) -> Result<Expression> {
+        let literal = self.current_token.token_literal();
+        // The lexer only produces decimal digits here, so the only failure is overflow
+        match literal.parse::<i64>() {
+            Result::Ok(value) => Ok(Expression::IntegerLiteral(value)),
+            Err(_) => bail!(ParserError::InvalidInteger(literal.to_string())),
+        }
+    }
+
+    fn parse_boolean(&self) -> Expression {
+        Expression::Boolean(self.current_token_is(Token::True))
+    }
+
+    fn parse_grouped_expression(&mut self) -> Result<Expression> {
+        self.next_token()?;
+
+        let expression = self.parse_expression(OperatorPrecedence::Lowest)?;
+
+        self.expect_peek(Token::RParen)?;
+
+        Ok(expression)
+    }
+
     fn read_identifier(&mut self) -> Result<&String> {
         match self.current_token {
             Token::Ident(ref identifier) => Ok(identifier),
+            Token::Illegal(ch) => bail!(ParserError::IllegalCharacter(ch)),
             _ => bail!(ParserError::MissingIdentifier(self.current_token.clone())),
         }
     }
 
+    /// Bails if `statement`, which started at `start`, consumed no token. The loop in
+    /// `parse_statements_with` always advances afterwards, so this can't hang; it catches a
+    /// statement parser that returned without parsing anything. Statement parsers stop on their
+    /// last token, so only an expression statement holding a single leaf expression may end on
+    /// the token it started on.
+    fn ensure_progress(&self, start: usize, statement: &Statement) -> Result<()> {
+        let single_token = match statement {
+            Statement::Expression(statement) => {
+                !matches!(statement.expression, Expression::Infix { .. })
+            }
+            _ => false,
+        };
+        if self.position == start && !single_token {
+            bail!(ParserError::NoProgress(self.current_token.clone()))
+        }
+        Ok(())
+    }
+
+    fn peek_precedence(&self) -> OperatorPrecedence {
+        token_precedence(&self.peek_token)
+    }
+
+    fn current_precedence(&self) -> OperatorPrecedence {
+        token_precedence(&self.current_token)
+    }
+
     fn current_token_is(&self, token: Token) -> bool {
         self.current_token == token
     }
@@ -189,6 +447,8 @@ impl Parser {
         if self.peek_token_is(&token) {
             self.next_token()?;
             Ok(())
+        } else if let Token::Illegal(ch) = self.peek_token {
+            bail!(ParserError::IllegalCharacter(ch))
         } else {
             bail!(ParserError::UnexpectedToken {
                 want: token.token_literal().to_string(),
@@ -202,16 +462,12 @@ mod test {
 
     use super::*;
 
-    fn let_statement_components(statement: &Statement, name: &str) -> Result<()> {
+    fn let_statement_components(statement: &Statement, name: &str, value: &str) -> Result<()> {
         match statement {
             Statement::Let(let_statement) => {
                 assert_eq!(let_statement.token_literal(), "let");
                 assert_eq!(let_statement.name.value, name);
-                // if let Expression::Identifier(ident) = &let_statement.value {
-                //     assert_eq!(ident.token_literal(), "let");
-                // } else {
-                //     bail!("let_statement.value is not Identifier");
-                // }
+                assert_eq!(let_statement.value.to_string(), value);
             }
             _ => bail!("statement not LetStatement"),
         }
@@ -223,24 +479,32 @@ mod test {
     fn let_statements() -> Result<()> {
         let input = r#"let x = 5;
         let y = 10;
-        let foobar = 838383;"#;
+        let foobar = 838383;
+        let z = y"#;
 
         let lexer = Lexer::new(input.into());
         let mut parser = Parser::new(lexer);
 
         let program = parser.parse_program()?;
 
-        if program.statements.len() != 3 {
+        if program.statements.len() != 4 {
             bail!(
-                "program.Statements does not contain 3 statements, got {}",
+                "program.Statements does not contain 4 statements, got {}",
                 program.statements.len()
             )
         }
 
-        let expected_identifiers = vec!["x", "y", "foobar"];
+        let expected = [("x", "5"), ("y", "10"), ("foobar", "838383"), ("z", "y")];
 
-        for (idx, ident) in expected_identifiers.iter().enumerate() {
-            let_statement_components(&program.statements[idx], ident)?;
+        for (idx, (ident, value)) in expected.iter().enumerate() {
+            let_statement_components(&program.statements[idx], ident, value)?;
+        }
+
+        match &program.statements[0] {
+            Statement::Let(let_statement) => {
+                assert!(matches!(let_statement.value, Expression::IntegerLiteral(5)))
+            }
+            _ => bail!("statement not LetStatement"),
         }
 
         Ok(())
@@ -250,29 +514,59 @@ mod test {
     fn return_statements() -> Result<()> {
         let input = r#"return 5;
         return 10;
-        return 993322; "#;
+        return 993322;
+        return ten
+        return;
+        return x + y; "#;
 
         let lexer = Lexer::new(input.into());
         let mut parser = Parser::new(lexer);
 
         let program = parser.parse_program()?;
 
-        if program.statements.len() != 3 {
+        if program.statements.len() != 6 {
             bail!(
-                "program.Statements does not contain 3 statements, got {}",
+                "program.Statements does not contain 6 statements, got {}",
                 program.statements.len()
             )
         }
 
-        for statement in program.statements {
+        let expected_values = [
+            Some("5"),
+            Some("10"),
+            Some("993322"),
+            Some("ten"),
+            None,
+            Some("(x + y)"),
+        ];
+
+        for (statement, expected) in program.statements.iter().zip(expected_values) {
             match statement {
                 Statement::Return(return_statement) => {
                     assert_eq!(return_statement.token_literal(), "return");
+                    assert_eq!(
+                        return_statement
+                            .return_value
+                            .as_ref()
+                            .map(|value| value.to_string()),
+                        expected.map(String::from)
+                    );
                 }
                 _ => bail!("statement not ReturnStatement"),
             }
         }
 
+        match &program.statements[5] {
+            Statement::Return(ReturnStatement {
+                return_value: Some(Expression::Infix { operator, .. }),
+                ..
+            }) => assert_eq!(*operator, Operator::Add),
+            _ => bail!("statement not ReturnStatement with an infix value"),
+        }
+
+        let program = parse("x; return")?;
+        assert_eq!(program.to_string(), "x;return;");
+
         Ok(())
     }
 
@@ -303,4 +597,369 @@ mod test {
         }
         Ok(())
     }
+
+    #[test]
+    fn integer_literal_expression() -> Result<()> {
+        let program = parse("5;")?;
+
+        match program.statements.as_slice() {
+            [Statement::Expression(expression_statement)] => {
+                assert!(matches!(
+                    expression_statement.expression,
+                    Expression::IntegerLiteral(5)
+                ))
+            }
+            _ => bail!("expected a single ExpressionStatement"),
+        }
+
+        let err = parse("9223372036854775808;").unwrap_err();
+        match err.downcast_ref::<ParserError>() {
+            Some(ParserError::InvalidInteger(literal)) => {
+                assert_eq!(literal, "9223372036854775808")
+            }
+            _ => bail!("expected ParserError::InvalidInteger, got {}", err),
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn infix_expressions() -> Result<()> {
+        let cases = [
+            ("1 + 2 * 3", "(1 + (2 * 3));"),
+            ("1 * 2 + 3", "((1 * 2) + 3);"),
+            ("a - b - c", "((a - b) - c);"),
+            ("a / b % c", "((a / b) % c);"),
+            ("a + b < c * d == e", "(((a + b) < (c * d)) == e);"),
+            ("a >= b != c <= d", "((a >= b) != (c <= d));"),
+            ("let x = 1 + 2; x * 3", "let x = (1 + 2);(x * 3);"),
+            ("return a + b;", "return (a + b);"),
+        ];
+
+        for (input, expect) in cases {
+            assert_eq!(parse(input)?.to_string(), expect, "input: {}", input);
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn boolean_expressions() -> Result<()> {
+        for (input, expect) in [("true;", true), ("false;", false)] {
+            let program = parse(input)?;
+            match program.statements.as_slice() {
+                [Statement::Expression(expression_statement)] => {
+                    match expression_statement.expression {
+                        Expression::Boolean(value) => assert_eq!(value, expect),
+                        _ => bail!("expression not Boolean"),
+                    }
+                }
+                _ => bail!("expected a single ExpressionStatement"),
+            }
+            assert_eq!(program.to_string(), input);
+        }
+
+        assert_eq!(parse("3 > 5 == false")?.to_string(), "((3 > 5) == false);");
+        assert_eq!(parse("let t = true;")?.to_string(), "let t = true;");
+
+        Ok(())
+    }
+
+    #[test]
+    fn grouped_expressions() -> Result<()> {
+        let cases = [
+            ("(1 + 2) * 3", "((1 + 2) * 3);"),
+            ("2 / (5 + 5)", "(2 / (5 + 5));"),
+            ("((a))", "a;"),
+            ("(5 + 5) * 2 == (a - b)", "(((5 + 5) * 2) == (a - b));"),
+        ];
+
+        for (input, expect) in cases {
+            assert_eq!(parse(input)?.to_string(), expect, "input: {}", input);
+        }
+
+        let err = parse("(1 + 2").unwrap_err();
+        match err.downcast_ref::<ParserError>() {
+            Some(ParserError::UnexpectedToken { want, .. }) => assert_eq!(want, ")"),
+            _ => bail!("expected ParserError::UnexpectedToken, got {}", err),
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn block_statement() -> Result<()> {
+        let mut parser = Parser::new(Lexer::new("{ let x = 5; x }".into()));
+
+        let block = parser.parse_block_statement()?;
+
+        assert_eq!(block.token_literal(), "{");
+        assert_eq!(block.statements.len(), 2);
+        assert!(matches!(block.statements[0], Statement::Let(_)));
+        assert!(matches!(block.statements[1], Statement::Expression(_)));
+        assert_eq!(block.to_string(), "let x = 5;x;");
+        assert_eq!(parser.current_token, Token::RBrace);
+
+        let mut parser = Parser::new(Lexer::new("{}".into()));
+        assert!(parser.parse_block_statement()?.statements.is_empty());
+
+        let mut parser = Parser::new(Lexer::new("{ let x = 5;".into()));
+        let err = parser.parse_block_statement().unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<ParserError>(),
+            Some(ParserError::UnclosedBlock)
+        ));
+
+        Ok(())
+    }
+
+    #[test]
+    fn precedence_table() {
+        let cases = [
+            (Token::Asterisk, OperatorPrecedence::Product),
+            (Token::Slash, OperatorPrecedence::Product),
+            (Token::Percent, OperatorPrecedence::Product),
+            (Token::Plus, OperatorPrecedence::Sum),
+            (Token::Minus, OperatorPrecedence::Sum),
+            (Token::LessThan, OperatorPrecedence::LessGreater),
+            (Token::GreaterEqual, OperatorPrecedence::LessGreater),
+            (Token::Equal, OperatorPrecedence::Equals),
+            (Token::NotEqual, OperatorPrecedence::Equals),
+            (Token::Semicolon, OperatorPrecedence::Lowest),
+            (Token::Ident("x".into()), OperatorPrecedence::Lowest),
+        ];
+
+        for (token, expect) in cases {
+            assert_eq!(token_precedence(&token), expect, "token: {}", token);
+        }
+    }
+
+    #[test]
+    fn no_progress_guard() -> Result<()> {
+        for (input, start) in [
+            ("let x = 5;", Token::Let),
+            ("foo;", Token::Ident("foo".into())),
+        ] {
+            let mut parser = Parser::new(Lexer::new(input.into()));
+            let mut program = Program {
+                statements: vec![],
+                blank_lines: vec![],
+            };
+
+            // A statement parser that returns an infix expression without consuming any token
+            let err = parser
+                .parse_statements_with(&mut program, |parser| {
+                    Ok(Statement::Expression(ExpressionStatement {
+                        token: parser.current_token.clone(),
+                        expression: Expression::Infix {
+                            left: Box::new(Expression::IntegerLiteral(1)),
+                            operator: Operator::Add,
+                            right: Box::new(Expression::IntegerLiteral(2)),
+                        },
+                    }))
+                })
+                .unwrap_err();
+
+            match err.downcast_ref::<ParserError>() {
+                Some(ParserError::NoProgress(token)) => assert_eq!(*token, start),
+                _ => bail!("expected ParserError::NoProgress, got {}", err),
+            }
+            assert!(program.statements.is_empty());
+        }
+
+        // Single-token statements end where they start without tripping the guard
+        assert_eq!(parse("foobar")?.to_string(), "foobar;");
+        assert_eq!(parse("x 5 true")?.to_string(), "x;5;true;");
+
+        Ok(())
+    }
+    #[test]
+    fn blank_lines_preserved() -> Result<()> {
+        let input = "x;\n\ny;\nz;\n\n\n\nw";
+
+        let lexer = Lexer::new(input.into());
+        let mut parser = Parser::new(lexer);
+
+        let program = parser.parse_program()?;
+
+        assert_eq!(program.blank_lines, vec![1, 3]);
+        assert_eq!(program.to_string(), "x;\n\ny;z;\n\nw;");
+
+        let program = parse("let x = 5;\n\nlet y = x;")?;
+        assert_eq!(program.blank_lines, vec![1]);
+        assert_eq!(program.to_string(), "let x = 5;\n\nlet y = x;");
+        Ok(())
+    }
+    #[test]
+    fn peek_token_lookahead() -> Result<()> {
+        let lexer = Lexer::new("1 + 2".into());
+        let mut parser = Parser::new(lexer);
+
+        assert_eq!(*parser.peek_token_n(1)?, Token::Plus);
+        assert_eq!(*parser.peek_token_n(0)?, Token::Int("1".into()));
+        assert_eq!(*parser.peek_token_n(3)?, Token::EOF);
+        assert_eq!(*parser.peek_token_n(2)?, Token::Int("2".into()));
+
+        // Buffered tokens come back in order once consumed
+        parser.next_token()?;
+        assert_eq!(parser.current_token, Token::Plus);
+        assert_eq!(parser.peek_token, Token::Int("2".into()));
+        parser.next_token()?;
+        assert_eq!(parser.peek_token, Token::EOF);
+
+        Ok(())
+    }
+    #[test]
+    fn parse_with_tokens_single_pass() -> Result<()> {
+        let input = "let x = 5;\nfoo;\n\nbar";
+
+        let (program, tokens, errors) = parse_with_tokens(input);
+
+        assert!(errors.is_empty());
+        assert_eq!(tokens, crate::lexer::tokenize(input)?);
+        assert_eq!(program.to_string(), parse(input)?.to_string());
+        assert_eq!(program.blank_lines, parse(input)?.blank_lines);
+
+        Ok(())
+    }
+
+    #[test]
+    fn parse_with_tokens_error() -> Result<()> {
+        let input = "foo; @ bar; baz";
+
+        let (program, tokens, errors) = parse_with_tokens(input);
+
+        assert_eq!(program.to_string(), "foo;");
+        assert_eq!(tokens, crate::lexer::tokenize(input)?);
+        assert!(matches!(
+            errors.as_slice(),
+            [ParserError::IllegalCharacter('@')]
+        ));
+        assert_eq!(errors[0].to_string(), "illegal character '@'");
+
+        let (_, _, errors) = parse_with_tokens("foo; \"bar");
+        assert!(matches!(
+            errors.as_slice(),
+            [ParserError::Lexer(LexerError::UnterminatedString)]
+        ));
+
+        let (_, tokens, errors) = parse_with_tokens("let s = \"a\\qb\"; let y = 2;");
+        let tokens: Vec<Token> = tokens.into_iter().map(|(token, _)| token).collect();
+        assert_eq!(
+            tokens,
+            vec![
+                Token::Let,
+                Token::Ident("s".into()),
+                Token::Assign,
+                Token::Semicolon,
+                Token::Let,
+                Token::Ident("y".into()),
+                Token::Assign,
+                Token::Int("2".into()),
+                Token::Semicolon,
+                Token::EOF,
+            ]
+        );
+        assert!(matches!(
+            errors.as_slice(),
+            [ParserError::Lexer(LexerError::UnknownEscape('q'))]
+        ));
+
+        let (program, tokens, errors) = parse_with_tokens("\"abc");
+        assert!(program.statements.is_empty());
+        assert_eq!(tokens, vec![(Token::EOF, 4..4)]);
+        assert!(matches!(
+            errors.as_slice(),
+            [ParserError::Lexer(LexerError::UnterminatedString)]
+        ));
+
+        Ok(())
+    }
+
+    #[test]
+    fn parse_with_tokens_keeps_lexing_after_errors() -> Result<()> {
+        let (program, tokens, errors) = parse_with_tokens("x y; 0xZZ; z 0b2");
+
+        // The error is hit while looking past `y;`, so that statement is lost with it
+        assert_eq!(program.to_string(), "x;");
+        let tokens: Vec<Token> = tokens.into_iter().map(|(token, _)| token).collect();
+        assert_eq!(
+            tokens,
+            vec![
+                Token::Ident("x".into()),
+                Token::Ident("y".into()),
+                Token::Semicolon,
+                Token::Semicolon,
+                Token::Ident("z".into()),
+                Token::EOF,
+            ]
+        );
+        match errors.as_slice() {
+            [ParserError::Lexer(LexerError::MalformedNumber(first)), ParserError::Lexer(LexerError::MalformedNumber(second))] =>
+            {
+                assert_eq!(first, "0xZZ");
+                assert_eq!(second, "0b2");
+            }
+            _ => bail!("expected two MalformedNumber errors, got {:?}", errors),
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn not_an_infix_operator() -> Result<()> {
+        let mut parser = Parser::new(Lexer::new("! 1".into()));
+        let err = parser
+            .parse_infix_expression(Expression::IntegerLiteral(1))
+            .unwrap_err();
+
+        match err.downcast_ref::<ParserError>() {
+            Some(ParserError::NotAnInfixOperator(token)) => assert_eq!(*token, Token::Bang),
+            _ => bail!("expected ParserError::NotAnInfixOperator, got {}", err),
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn illegal_character() -> Result<()> {
+        for input in ["let x = @;", "let @ = 5;", "let x @ 5;"] {
+            let err = parse(input).unwrap_err();
+
+            assert!(matches!(
+                err.downcast_ref::<ParserError>(),
+                Some(ParserError::IllegalCharacter('@'))
+            ));
+            assert_eq!(err.to_string(), "illegal character '@'");
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn parser_error_from_other_errors() {
+        let err = ParserError::from(anyhow!("something else went wrong"));
+        assert!(
+            matches!(err, ParserError::Other(ref message) if message == "something else went wrong")
+        );
+    }
+
+    #[test]
+    fn lexer_error_in_first_tokens() -> Result<()> {
+        for input in ["\"abc", "x \"abc"] {
+            let err = parse(input).unwrap_err();
+            assert!(matches!(
+                err.downcast_ref::<LexerError>(),
+                Some(LexerError::UnterminatedString)
+            ));
+        }
+
+        let err = parse("0xZZ").unwrap_err();
+        match err.downcast_ref::<LexerError>() {
+            Some(LexerError::MalformedNumber(literal)) => assert_eq!(literal, "0xZZ"),
+            _ => bail!("expected LexerError::MalformedNumber, got {}", err),
+        }
+
+        Ok(())
+    }
 }
@@ -1,9 +1,12 @@
 use std::io::{stdin, stdout, Write};
 
-use crate::lexer;
+use crate::{lexer, parser::Parser};
 const PROMPT: &str = ">> ";
+const AST_COMMAND: &str = ".ast";
 
 pub fn start() {
+    let mut last_line = None;
+
     loop {
         print!("{}", PROMPT);
         stdout().flush().unwrap();
@@ -15,13 +18,123 @@ pub fn start() {
             break;
         }
 
-        let mut lexer = lexer::Lexer::new(line);
+        print!("{}", handle_line(line.trim(), &mut last_line));
+    }
+}
+
+/// Runs a single REPL line and returns what should be printed for it. `.ast <source>` shows
+/// the parse tree of `<source>`, or of the previous line when no source is given.
+fn handle_line(line: &str, last_line: &mut Option<String>) -> String {
+    let command = line
+        .strip_prefix(AST_COMMAND)
+        .filter(|rest| rest.is_empty() || rest.starts_with(char::is_whitespace));
+    if let Some(source) = command {
+        let source = match source.trim() {
+            "" => last_line.as_deref(),
+            source => Some(source),
+        };
+
+        return match source {
+            Some(source) => show_ast(source),
+            None => "No previous input to show\n".to_string(),
+        };
+    }
+
+    *last_line = Some(line.to_string());
+
+    let mut output = String::new();
+    let mut lexer = lexer::Lexer::new(line.to_string());
 
-        while let Ok(token) = lexer.next_token() {
-            if token == lexer::Token::EOF {
-                break;
-            }
-            println!("{:?}", token);
+    loop {
+        match lexer.next_token() {
+            Ok(lexer::Token::EOF) => break,
+            Ok(token) => output.push_str(&format!("{:?}\n", token)),
+            Err(err) => output.push_str(&format!("{}\n", err)),
         }
     }
+
+    output
+}
+
+fn show_ast(source: &str) -> String {
+    let mut parser = Parser::new(lexer::Lexer::new(source.to_string()));
+
+    match parser.parse_program() {
+        Ok(program) => program.debug_tree(),
+        Err(err) => format!("{}\n", err),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::handle_line;
+
+    #[test]
+    fn ast_command() {
+        let mut last_line = None;
+
+        assert_eq!(
+            handle_line(".ast foo", &mut last_line),
+            "Program\n  ExpressionStatement\n    Identifier foo\n"
+        );
+        assert_eq!(last_line, None);
+
+        assert_eq!(
+            handle_line(".ast 1 + 2 * 3", &mut last_line),
+            "\
+Program
+  ExpressionStatement
+    Infix +
+      IntegerLiteral 1
+      Infix *
+        IntegerLiteral 2
+        IntegerLiteral 3
+"
+        );
+    }
+
+    #[test]
+    fn ast_command_uses_last_line() {
+        let mut last_line = None;
+
+        assert_eq!(
+            handle_line(".ast", &mut last_line),
+            "No previous input to show\n"
+        );
+
+        assert_eq!(handle_line("bar", &mut last_line), "Ident(\"bar\")\n");
+        assert_eq!(
+            handle_line(".ast", &mut last_line),
+            "Program\n  ExpressionStatement\n    Identifier bar\n"
+        );
+
+        assert_eq!(
+            handle_line(".ast @", &mut last_line),
+            "illegal character '@'\n"
+        );
+
+        // Only `.ast` on its own or followed by whitespace is the command
+        assert_eq!(
+            handle_line(".astfoo", &mut last_line),
+            "Dot\nIdent(\"astfoo\")\n"
+        );
+    }
+
+    #[test]
+    fn lexer_errors_are_printed() {
+        let mut last_line = None;
+
+        assert_eq!(
+            handle_line(".ast \"abc", &mut last_line),
+            "String literal is missing its closing quote\n"
+        );
+        assert_eq!(
+            handle_line(".ast 0xZZ", &mut last_line),
+            "Malformed number literal 0xZZ\n"
+        );
+        assert_eq!(
+            handle_line("x 0xZZ y", &mut last_line),
+            "Ident(\"x\")\nMalformed number literal 0xZZ\nIdent(\"y\")\n"
+        );
+    }
 }